@@ -0,0 +1,80 @@
+use std::env;
+
+/// Environment variables that coding agents set in the shells they spawn,
+/// checked in order. A trailing `*` matches any variable with that prefix.
+const AGENT_VARS: &[(&str, &str)] = &[
+    ("CLAUDECODE", "claude"),
+    ("GEMINI_CLI", "gemini"),
+    ("CODEX_*", "codex"),
+    ("CURSOR_AGENT", "cursor"),
+    ("CLINE_ACTIVE", "cline"),
+    ("OPENCODE", "opencode"),
+];
+
+/// Recorded when no agent is detected.
+pub const HUMAN: &str = "human";
+
+fn non_empty(var: &str) -> Option<String> {
+    env::var(var).ok().filter(|v| !v.trim().is_empty())
+}
+
+/// The agent whose environment variables appear in `vars`, if any.
+fn detect(vars: &[(String, String)]) -> Option<&'static str> {
+    AGENT_VARS.iter().find_map(|&(pattern, agent)| {
+        let matches = |name: &str| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == pattern,
+        };
+        vars.iter()
+            .any(|(name, value)| matches(name) && !value.is_empty())
+            .then_some(agent)
+    })
+}
+
+/// Who is writing: `--actor`, else `TASK_ACTOR`, else a detected agent, else
+/// `human`.
+pub fn actor(flag: Option<String>) -> String {
+    flag.filter(|a| !a.trim().is_empty())
+        .or_else(|| non_empty("TASK_ACTOR"))
+        .or_else(|| detect(&env::vars().collect::<Vec<_>>()).map(str::to_string))
+        .unwrap_or_else(|| HUMAN.to_string())
+}
+
+/// Session ID from `TASK_SESSION`, to tie entries to an agent's session log.
+pub fn session() -> Option<String> {
+    non_empty("TASK_SESSION")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn detects_known_agents() {
+        assert_eq!(detect(&vars(&[("CLAUDECODE", "1")])), Some("claude"));
+        assert_eq!(detect(&vars(&[("GEMINI_CLI", "1")])), Some("gemini"));
+        assert_eq!(
+            detect(&vars(&[("CODEX_SANDBOX", "seatbelt")])),
+            Some("codex")
+        );
+    }
+
+    #[test]
+    fn ignores_unrelated_and_empty_vars() {
+        assert_eq!(detect(&vars(&[("PATH", "/bin"), ("CLAUDECODE", "")])), None);
+        assert_eq!(detect(&vars(&[])), None);
+    }
+
+    #[test]
+    fn earlier_agents_win() {
+        let both = vars(&[("CODEX_SANDBOX", "1"), ("CLAUDECODE", "1")]);
+        assert_eq!(detect(&both), Some("claude"));
+    }
+}
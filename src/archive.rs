@@ -0,0 +1,56 @@
+use crate::store::{EntryKind, LogLock, Origin, TaskEntry, TaskStore};
+use crate::time::{before_now, parse_ts};
+use chrono::Duration;
+
+fn append_archived(lock: &mut LogLock, task: &TaskEntry, origin: &Origin) -> TaskEntry {
+    let mut entry = task.successor(task.status.clone(), "archived".into());
+    entry.kind = Some(EntryKind::Archive);
+    entry.archived = true;
+    entry.release();
+    entry.stamp(origin);
+    lock.append(&entry);
+    entry
+}
+
+/// Archive the given tasks, skipping ones already archived. Every ID is
+/// resolved before anything is appended. Returns the appended entries.
+pub fn archive(
+    store: &TaskStore,
+    ids: &[String],
+    origin: &Origin,
+) -> Result<Vec<TaskEntry>, String> {
+    let mut lock = store.lock()?;
+    let snapshot = store.snapshot();
+    let tasks = ids
+        .iter()
+        .map(|id| snapshot.resolve(id))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut archived = Vec::new();
+    for task in tasks {
+        if !task.archived && !archived.iter().any(|e: &TaskEntry| e.id == task.id) {
+            archived.push(append_archived(&mut lock, task, origin));
+        }
+    }
+    Ok(archived)
+}
+
+/// Archive tasks (in `project`, or everywhere when `None`) in one of the
+/// `terminal` statuses whose last entry is older than `older_than`.
+pub fn archive_done_before(
+    store: &TaskStore,
+    project: Option<&str>,
+    terminal: &[String],
+    older_than: Duration,
+    origin: &Origin,
+) -> Result<Vec<TaskEntry>, String> {
+    let cutoff = before_now(older_than)?;
+    let mut lock = store.lock()?;
+    let archived = store
+        .current_tasks(project, None)
+        .iter()
+        .filter(|t| !t.archived && terminal.contains(&t.status))
+        .filter(|t| parse_ts(&t.ts).is_some_and(|ts| ts < cutoff))
+        .map(|t| append_archived(&mut lock, t, origin))
+        .collect();
+    Ok(archived)
+}
@@ -0,0 +1,98 @@
+use crate::graph::TaskGraph;
+use crate::store::{Origin, TaskEntry, TaskStore};
+use crate::time::format_ts;
+use crate::workflow::Workflow;
+use chrono::{Duration, Local};
+
+pub struct ClaimRequest<'a> {
+    pub agent: &'a str,
+    pub ttl: Duration,
+    /// Scope of `--next`.
+    pub project: &'a str,
+    /// Let `--next` pick from every project.
+    pub all: bool,
+    /// Statuses an explicitly named task cannot be claimed from.
+    pub terminal: &'a [String],
+    /// Decides the status a claim moves to, and which moves are allowed.
+    pub workflow: &'a Workflow,
+    /// Claim an explicitly named task even if it is finished or waiting on
+    /// unfinished dependencies, or the workflow forbids the move.
+    pub force: bool,
+    pub origin: &'a Origin,
+}
+
+/// Claim `id` (or, when `None`, the highest-priority claimable task) for
+/// `req.agent`, appending a `doing` entry (or whatever the workflow aliases
+/// `doing` to) that records the lease.
+///
+/// Holds the log lock across the check and the append, so two agents racing
+/// for the same task cannot both succeed. Re-claiming a task the agent
+/// already holds renews the lease. A named task must not be finished or have
+/// unfinished dependencies, unless `req.force` is set.
+pub fn claim(store: &TaskStore, id: Option<&str>, req: &ClaimRequest) -> Result<TaskEntry, String> {
+    let mut lock = store.lock()?;
+    let now = Local::now();
+    let tasks = store.current_tasks(None, None);
+    let graph = TaskGraph::new(&tasks).with_workflow(req.workflow);
+
+    let prev = match id {
+        Some(id) => {
+            let id = store.resolve_id(id)?;
+            let task = tasks.iter().find(|t| t.id == id).unwrap();
+            if let Some(holder) = task.live_claim(now)
+                && holder != req.agent
+            {
+                return Err(format!(
+                    "task '{id}' is claimed by {holder} until {}",
+                    task.lease_until.as_deref().unwrap_or("?")
+                ));
+            }
+            if !req.force {
+                if req.terminal.contains(&task.status) {
+                    return Err(format!(
+                        "task '{id}' is {}; use --force to claim it anyway",
+                        task.status
+                    ));
+                }
+                let waiting = graph.unfinished_dependencies(task);
+                if !waiting.is_empty() {
+                    return Err(format!(
+                        "task '{id}' is waiting on {}; use --force to claim it anyway",
+                        waiting.join(", ")
+                    ));
+                }
+            }
+            task
+        }
+        None => {
+            let mut candidates: Vec<&TaskEntry> = tasks
+                .iter()
+                .filter(|t| req.all || t.project == req.project)
+                .filter(|t| !t.archived)
+                .filter(|t| graph.is_claimable(t, now))
+                .collect();
+            candidates.sort_by_key(|t| t.priority_rank());
+            candidates
+                .first()
+                .copied()
+                .ok_or_else(|| "no ready task to claim".to_string())?
+        }
+    };
+
+    let doing = if req.force {
+        req.workflow.canonical("doing")
+    } else {
+        let doing = req.workflow.resolve_builtin("doing", "task claim")?;
+        req.workflow.check_transition(&prev.status, &doing)?;
+        doing
+    };
+    let mut entry = prev.successor(doing, format!("claimed by {}", req.agent));
+    entry.claimed_by = Some(req.agent.to_string());
+    let lease_until = now
+        .checked_add_signed(req.ttl)
+        .ok_or_else(|| "--ttl is out of range".to_string())?;
+    entry.lease_until = Some(format_ts(lease_until));
+    entry.stamp(req.origin);
+    lock.append(&entry);
+    Ok(entry)
+}
@@ -0,0 +1,179 @@
+use crate::store::{EntryKind, TaskEntry, TaskStore, latest_states};
+use crate::time::{before_now, parse_ts};
+use chrono::{DateTime, Duration, Local};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::Write as _;
+
+#[derive(Debug, Default, Serialize)]
+pub struct CompactReport {
+    /// IDs of the tasks whose history was moved.
+    pub tasks: Vec<String>,
+    pub entries: usize,
+    /// Segment files written to (or that would be, with `dry_run`).
+    pub segments: Vec<String>,
+}
+
+/// Tasks finished (in one of the `terminal` statuses, or archived) with no
+/// entry since `cutoff`. Tasks that a remaining task depends on or is a
+/// sub-task of stay, and so do sub-tasks of a remaining task, so readiness
+/// and roll-ups don't change: finished sub-trees move only as a whole.
+fn select(tasks: &[TaskEntry], terminal: &[String], cutoff: DateTime<Local>) -> HashSet<String> {
+    let mut selected: HashSet<String> = tasks
+        .iter()
+        .filter(|t| t.archived || terminal.contains(&t.status))
+        .filter(|t| parse_ts(&t.ts).is_some_and(|ts| ts < cutoff))
+        .map(|t| t.id.clone())
+        .collect();
+    let present: HashSet<&str> = tasks.iter().map(|t| t.id.as_str()).collect();
+    loop {
+        let referenced = tasks
+            .iter()
+            .filter(|t| !selected.contains(&t.id))
+            .flat_map(|t| t.depends_on.iter().chain(&t.parent))
+            .filter(|id| selected.contains(*id));
+        let orphaned = tasks
+            .iter()
+            .filter(|t| selected.contains(&t.id))
+            .filter(|t| {
+                t.parent
+                    .as_deref()
+                    .is_some_and(|p| present.contains(p) && !selected.contains(p))
+            })
+            .map(|t| &t.id);
+        let needed: Vec<String> = referenced.chain(orphaned).cloned().collect();
+        if needed.is_empty() {
+            return selected;
+        }
+        for id in needed {
+            selected.remove(&id);
+        }
+    }
+}
+
+fn write_segment(path: &std::path::Path, lines: &[&str]) -> Result<(), String> {
+    let err = |e: std::io::Error| format!("failed to write {}: {e}", path.display());
+    // A rerun after a crash between writing segments and replacing the log
+    // must not duplicate entries.
+    let existing = fs::read_to_string(path).unwrap_or_default();
+    let existing: HashSet<&str> = existing.lines().collect();
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(err)?;
+    for line in lines.iter().filter(|l| !existing.contains(*l)) {
+        writeln!(file, "{line}").map_err(err)?;
+    }
+    file.sync_all().map_err(err)
+}
+
+/// Move the full history of finished tasks out of the log into monthly
+/// segments named after the month of each task's last entry.
+///
+/// Runs under the log lock. Segments are written and synced first, along
+/// with the index of compacted IDs (`TaskStore::compacted_ids`), then the
+/// log is rewritten to a temporary file and renamed into place, so a crash
+/// at any point loses nothing. Writers waiting on the lock notice the log
+/// was replaced and append to the new one (see `TaskStore::lock`).
+pub fn compact(
+    store: &TaskStore,
+    terminal: &[String],
+    older_than: Duration,
+    dry_run: bool,
+) -> Result<CompactReport, String> {
+    let cutoff = before_now(older_than)?;
+    let lock = store.lock()?;
+    let path = store.path();
+    let content = match fs::read(path) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(_) => return Ok(CompactReport::default()),
+    };
+
+    let parsed: Vec<(&str, Option<TaskEntry>)> = content
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| (l, TaskEntry::parse(l).ok()))
+        .collect();
+    let entries: Vec<TaskEntry> = parsed
+        .iter()
+        .filter_map(|(_, e)| e.clone())
+        .filter(|e| e.kind != Some(EntryKind::Unknown))
+        .collect();
+    let tasks = latest_states(&entries);
+    let selected = select(&tasks, terminal, cutoff);
+    if selected.is_empty() {
+        return Ok(CompactReport::default());
+    }
+
+    let month_of: BTreeMap<&str, String> = tasks
+        .iter()
+        .filter(|t| selected.contains(&t.id))
+        .map(|t| {
+            let month = parse_ts(&t.ts).map_or_else(
+                || Local::now().format("%Y-%m").to_string(),
+                |ts| ts.format("%Y-%m").to_string(),
+            );
+            (t.id.as_str(), month)
+        })
+        .collect();
+    // Bad lines already copied aside by `task doctor --quarantine` are
+    // dropped; any others stay in the log.
+    let quarantined: HashSet<String> = store.quarantined().into_iter().map(|b| b.raw).collect();
+    let mut keep: Vec<&str> = Vec::new();
+    let mut moved: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (line, entry) in &parsed {
+        match entry {
+            Some(e) if e.kind != Some(EntryKind::Unknown) && selected.contains(&e.id) => {
+                moved
+                    .entry(&month_of[e.id.as_str()])
+                    .or_default()
+                    .push(line);
+            }
+            None if quarantined.contains(*line) => {}
+            _ => keep.push(line),
+        }
+    }
+
+    let report = CompactReport {
+        tasks: tasks
+            .iter()
+            .filter(|t| selected.contains(&t.id))
+            .map(|t| t.id.clone())
+            .collect(),
+        entries: moved.values().map(Vec::len).sum(),
+        segments: moved
+            .keys()
+            .map(|month| {
+                let segment = store.segment_path(month);
+                segment
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect(),
+    };
+    if dry_run {
+        return Ok(report);
+    }
+
+    for (month, lines) in &moved {
+        write_segment(&store.segment_path(month), lines)?;
+    }
+    store.add_compacted_ids(&report.tasks)?;
+    let tmp = store.sidecar(&format!("compact.tmp.{}", std::process::id()));
+    let err = |e: std::io::Error| format!("failed to rewrite {}: {e}", path.display());
+    let mut file = fs::File::create(&tmp).map_err(err)?;
+    for line in &keep {
+        writeln!(file, "{line}").map_err(err)?;
+    }
+    file.sync_all().map_err(err)?;
+    fs::rename(&tmp, path).map_err(err)?;
+    // The cached snapshot describes the old log; drop it rather than rely on
+    // the offset check noticing the rewrite.
+    let _ = fs::remove_file(store.sidecar("snapshot"));
+    drop(lock);
+    Ok(report)
+}
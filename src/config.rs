@@ -0,0 +1,401 @@
+use crate::output::Column;
+use crate::store::IdFormat;
+use crate::workflow::Workflow;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const PROJECT_FILE: &str = ".task.toml";
+
+/// Keys understood in config files, in display order.
+pub const KEYS: &[&str] = &[
+    "log_path",
+    "project",
+    "default_status",
+    "terminal_statuses",
+    "columns",
+    "id_format",
+    "workflow",
+];
+
+/// Contents of one config file. Every key is optional.
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    log_path: Option<String>,
+    project: Option<String>,
+    default_status: Option<String>,
+    terminal_statuses: Option<Vec<String>>,
+    columns: Option<Vec<String>>,
+    workflow: Option<Workflow>,
+}
+
+/// Where the effective value of a key came from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    Env(&'static str),
+    Global(PathBuf),
+    Project(PathBuf),
+    /// A file kept next to the log, for settings shared by all its writers.
+    Log(PathBuf),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::Env(var) => write!(f, "env {var}"),
+            Source::Global(path) => write!(f, "global {}", path.display()),
+            Source::Project(path) => write!(f, "project {}", path.display()),
+            Source::Log(path) => write!(f, "log {}", path.display()),
+        }
+    }
+}
+
+/// One row of `task config list`.
+#[derive(Debug, Serialize)]
+pub struct Setting {
+    pub key: &'static str,
+    pub value: toml::Value,
+    pub source: String,
+}
+
+/// Effective configuration: the global file
+/// (`$XDG_CONFIG_HOME/task/config.toml`, default `~/.config/task/config.toml`)
+/// overlaid by the nearest `.task.toml` found walking up from the cwd.
+/// `TASK_LOG_PATH` still wins over both for the log path.
+#[derive(Debug, Default)]
+pub struct Config {
+    pub log_path: Option<PathBuf>,
+    pub project: Option<String>,
+    pub default_status: Option<String>,
+    pub terminal_statuses: Option<Vec<String>>,
+    pub columns: Option<Vec<Column>>,
+    /// Not read from config files; see `TaskStore::id_format`.
+    pub id_format: Option<IdFormat>,
+    /// The project workflow replaces the global one as a whole.
+    pub workflow: Workflow,
+    sources: BTreeMap<&'static str, Source>,
+}
+
+impl Config {
+    pub fn source(&self, key: &str) -> Source {
+        self.sources.get(key).cloned().unwrap_or(Source::Default)
+    }
+
+    pub fn default_status(&self) -> &str {
+        self.default_status.as_deref().unwrap_or("todo")
+    }
+
+    /// Record the ID format stored with the log at `path`, so that
+    /// `task config` shows it alongside the file settings.
+    pub fn apply_id_format(&mut self, format: IdFormat, path: PathBuf) {
+        self.id_format = Some(format);
+        self.sources.insert("id_format", Source::Log(path));
+    }
+
+    /// Statuses `task list` hides unless asked for, and that
+    /// `task archive --done-before` archives. Defaults to `done`, or what the
+    /// workflow aliases it to.
+    pub fn terminal_statuses(&self) -> Vec<String> {
+        self.terminal_statuses
+            .clone()
+            .unwrap_or_else(|| vec![self.workflow.canonical("done")])
+    }
+
+    /// Effective value and source of every key. `log_path` and `project`
+    /// default to values computed elsewhere, so the caller passes them in.
+    pub fn settings(&self, log_path: &Path, project: &str) -> Vec<Setting> {
+        KEYS.iter()
+            .map(|&key| Setting {
+                key,
+                value: self.value(key, log_path, project),
+                source: self.source(key).to_string(),
+            })
+            .collect()
+    }
+
+    fn value(&self, key: &str, log_path: &Path, project: &str) -> toml::Value {
+        match key {
+            "log_path" => log_path.display().to_string().into(),
+            "project" => project.into(),
+            "default_status" => self.default_status().into(),
+            "terminal_statuses" => self.terminal_statuses().into(),
+            "columns" => self
+                .columns
+                .as_deref()
+                .unwrap_or(Column::DEFAULT)
+                .iter()
+                .map(|c| toml::Value::from(c.name()))
+                .collect::<Vec<_>>()
+                .into(),
+            "id_format" => self.id_format.unwrap_or_default().to_string().into(),
+            "workflow" => toml::Value::try_from(&self.workflow).expect("workflow is valid TOML"),
+            _ => unreachable!("unknown config key {key}"),
+        }
+    }
+
+    fn apply(&mut self, file: ConfigFile, source: &Source, dir: &Path) -> Result<(), String> {
+        if let Some(path) = file.log_path {
+            self.log_path = Some(resolve_path(&path, dir));
+            self.sources.insert("log_path", source.clone());
+        }
+        if let Some(project) = file.project {
+            self.project = Some(project);
+            self.sources.insert("project", source.clone());
+        }
+        if let Some(status) = file.default_status {
+            self.default_status = Some(status);
+            self.sources.insert("default_status", source.clone());
+        }
+        if let Some(statuses) = file.terminal_statuses {
+            self.terminal_statuses = Some(statuses);
+            self.sources.insert("terminal_statuses", source.clone());
+        }
+        if let Some(columns) = file.columns {
+            self.columns = Some(parse_columns(&columns)?);
+            self.sources.insert("columns", source.clone());
+        }
+        if let Some(workflow) = file.workflow {
+            self.workflow = workflow;
+            self.sources.insert("workflow", source.clone());
+        }
+        Ok(())
+    }
+}
+
+fn parse_columns(names: &[String]) -> Result<Vec<Column>, String> {
+    if names.is_empty() {
+        return Err("columns must not be empty".to_string());
+    }
+    names.iter().map(|name| name.parse()).collect()
+}
+
+/// Expand a leading `~/` and make relative paths relative to the config file.
+fn resolve_path(path: &str, dir: &Path) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => dir.join(path),
+    }
+}
+
+pub fn global_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("task/config.toml"))
+}
+
+/// Nearest `.task.toml` in `start` or one of its ancestors.
+pub fn find_project_file(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+fn parse_file(text: &str, path: &Path) -> Result<ConfigFile, String> {
+    let file: ConfigFile =
+        toml::from_str(text).map_err(|e| format!("invalid config {}: {e}", path.display()))?;
+    if let Some(columns) = &file.columns {
+        parse_columns(columns).map_err(|e| format!("invalid config {}: {e}", path.display()))?;
+    }
+    Ok(file)
+}
+
+fn read_file(path: &Path) -> Result<ConfigFile, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    parse_file(&text, path)
+}
+
+pub fn load() -> Result<Config, String> {
+    let cwd = env::current_dir().unwrap_or_default();
+    let layers = [
+        global_path().map(|path| (path.clone(), Source::Global(path))),
+        find_project_file(&cwd).map(|path| (path.clone(), Source::Project(path))),
+    ];
+    let mut config = Config::default();
+    for (path, source) in layers.into_iter().flatten() {
+        if !path.is_file() {
+            continue;
+        }
+        let file = read_file(&path)?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        config.apply(file, &source, dir)?;
+    }
+    if let Some(path) = env::var_os("TASK_LOG_PATH") {
+        config.log_path = Some(PathBuf::from(path));
+        config
+            .sources
+            .insert("log_path", Source::Env("TASK_LOG_PATH"));
+    }
+    Ok(config)
+}
+
+/// Turn the command-line value of `key` into TOML. List keys take a
+/// comma-separated list; `workflow` is a table and must be edited by hand.
+fn parse_value(key: &str, value: &str) -> Result<toml::Value, String> {
+    match key {
+        "log_path" | "project" | "default_status" => Ok(value.into()),
+        "columns" | "terminal_statuses" => {
+            let names: Vec<String> = value
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+            if key == "columns" {
+                parse_columns(&names)?;
+            }
+            Ok(names.into())
+        }
+        "workflow" => Err("workflow is a table; edit the config file directly".to_string()),
+        "id_format" => Err("id_format is kept with the log, not in config files".to_string()),
+        _ => Err(format!(
+            "unknown config key '{key}' (expected {})",
+            KEYS.join(", ")
+        )),
+    }
+}
+
+/// Replace the top-level `key = ...` line of `text`, or add one after the
+/// last top-level line. Other lines, comments included, are kept as they are.
+fn set_key(text: &str, key: &str, value: &toml::Value) -> String {
+    let line = format!("{key} = {value}");
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let top_level = lines
+        .iter()
+        .position(|l| l.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+    let existing = lines[..top_level].iter().position(|l| {
+        l.split_once('=')
+            .is_some_and(|(k, _)| k.trim().trim_matches('"') == key)
+    });
+    match existing {
+        Some(i) => lines[i] = line,
+        None => {
+            let end = lines[..top_level]
+                .iter()
+                .rposition(|l| !l.trim().is_empty())
+                .map_or(0, |i| i + 1);
+            lines.insert(end, line);
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Write `key = value` into `path`, creating the file if needed. The result
+/// is validated before anything is written.
+pub fn set(path: &Path, key: &str, value: &str) -> Result<(), String> {
+    let value = parse_value(key, value)?;
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("failed to read {}: {e}", path.display())),
+    };
+    let updated = set_key(&text, key, &value);
+    parse_file(&updated, path)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    }
+    fs::write(path, updated).map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_project_file_walks_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a/b");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_project_file(&nested), None);
+        fs::write(dir.path().join(PROJECT_FILE), "").unwrap();
+        assert_eq!(
+            find_project_file(&nested),
+            Some(dir.path().join(PROJECT_FILE))
+        );
+    }
+
+    #[test]
+    fn read_file_parses_workflow() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PROJECT_FILE);
+        fs::write(&path, "[workflow]\nstatuses = [\"todo\", \"done\"]\n").unwrap();
+        let file = read_file(&path).unwrap();
+        assert_eq!(file.workflow.unwrap().statuses, vec!["todo", "done"]);
+    }
+
+    #[test]
+    fn read_file_reports_invalid_toml() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PROJECT_FILE);
+        fs::write(&path, "[workflow\n").unwrap();
+        assert!(read_file(&path).unwrap_err().contains("invalid config"));
+        fs::write(&path, "columns = [\"id\", \"bogus\"]\n").unwrap();
+        assert!(
+            read_file(&path)
+                .unwrap_err()
+                .contains("unknown column 'bogus'")
+        );
+    }
+
+    #[test]
+    fn later_layers_override_earlier_ones() {
+        let global = Source::Global("/g/config.toml".into());
+        let project = Source::Project("/p/.task.toml".into());
+        let mut config = Config::default();
+        let file = |text: &str| parse_file(text, Path::new("x")).unwrap();
+        config
+            .apply(
+                file("default_status = \"inbox\"\nproject = \"g\"\n"),
+                &global,
+                Path::new("/g"),
+            )
+            .unwrap();
+        config
+            .apply(
+                file("project = \"p\"\nlog_path = \"logs/tasks.log\"\n"),
+                &project,
+                Path::new("/p"),
+            )
+            .unwrap();
+        assert_eq!(config.default_status(), "inbox");
+        assert_eq!(config.source("default_status"), global);
+        assert_eq!(config.project.as_deref(), Some("p"));
+        assert_eq!(config.source("project"), project);
+        assert_eq!(config.log_path, Some(PathBuf::from("/p/logs/tasks.log")));
+        assert_eq!(config.source("columns"), Source::Default);
+    }
+
+    #[test]
+    fn set_key_replaces_or_inserts_top_level_keys() {
+        let text = "# comment\nproject = \"old\"\n\n[workflow]\nstatuses = [\"todo\"]\n";
+        let updated = set_key(text, "project", &"new".into());
+        assert_eq!(
+            updated,
+            "# comment\nproject = \"new\"\n\n[workflow]\nstatuses = [\"todo\"]\n"
+        );
+        let updated = set_key(text, "default_status", &"inbox".into());
+        assert!(updated.contains("default_status = \"inbox\"\n\n[workflow]"));
+        assert_eq!(set_key("", "project", &"p".into()), "project = \"p\"\n");
+    }
+
+    #[test]
+    fn set_validates_before_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested/config.toml");
+        assert!(set(&path, "columns", "id,bogus").is_err());
+        assert!(set(&path, "nope", "x").is_err());
+        assert!(set(&path, "workflow", "x").is_err());
+        assert!(!path.exists());
+        set(&path, "columns", "id, title").unwrap();
+        let file = read_file(&path).unwrap();
+        assert_eq!(file.columns.unwrap(), vec!["id", "title"]);
+    }
+}
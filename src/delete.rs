@@ -0,0 +1,88 @@
+use crate::store::{EntryKind, Origin, SCHEMA_VERSION, TaskEntry, TaskStore, effective_history};
+use crate::time::now_ts;
+
+/// Append a tombstone for each task, hiding it from every view but
+/// `task get`. Every ID is resolved before anything is appended. Unless
+/// `force` is set, tasks that other remaining tasks depend on or are
+/// sub-tasks of are refused, since those would never become ready or would
+/// lose their parent. Returns the appended entries.
+pub fn delete(
+    store: &TaskStore,
+    ids: &[String],
+    force: bool,
+    origin: &Origin,
+) -> Result<Vec<TaskEntry>, String> {
+    let mut lock = store.lock()?;
+    let snapshot = store.snapshot();
+    let tasks = ids
+        .iter()
+        .map(|id| snapshot.resolve(id))
+        .collect::<Result<Vec<_>, _>>()?;
+    if !force {
+        let ids: Vec<String> = tasks.iter().map(|t| t.id.clone()).collect();
+        let referrers = referrers(&snapshot.live_tasks(), &ids);
+        if !referrers.is_empty() {
+            return Err(format!(
+                "other tasks still refer to the task(s) to delete:\n{}\ndelete those too, or use --force",
+                referrers.join("\n")
+            ));
+        }
+    }
+    let mut deleted: Vec<TaskEntry> = Vec::new();
+    for task in tasks {
+        if deleted.iter().any(|e| e.id == task.id) {
+            continue;
+        }
+        let mut entry = task.successor(task.status.clone(), "deleted".into());
+        entry.kind = Some(EntryKind::Delete);
+        entry.deleted = true;
+        entry.release();
+        entry.stamp(origin);
+        lock.append(&entry);
+        deleted.push(entry);
+    }
+    Ok(deleted)
+}
+
+/// Tasks outside `ids` that depend on, or are sub-tasks of, one of `ids`,
+/// one line each.
+fn referrers(tasks: &[TaskEntry], ids: &[String]) -> Vec<String> {
+    tasks
+        .iter()
+        .filter(|t| !ids.contains(&t.id))
+        .flat_map(|t| {
+            let deps = t
+                .depends_on
+                .iter()
+                .filter(|d| ids.contains(d))
+                .map(move |d| format!("  {}  {} (depends on {d})", t.id, t.title));
+            let parent = t
+                .parent
+                .iter()
+                .filter(|p| ids.contains(p))
+                .map(move |p| format!("  {}  {} (sub-task of {p})", t.id, t.title));
+            deps.chain(parent)
+        })
+        .collect()
+}
+
+/// Append an entry restoring the state before the task's last change, which
+/// may itself be a tombstone or an earlier undo.
+pub fn undo(store: &TaskStore, id: &str, origin: &Origin) -> Result<TaskEntry, String> {
+    let mut lock = store.lock()?;
+    let id = store.resolve_any_id(id)?;
+    let entries = store.entries_for_id(&id);
+    let states = effective_history(&entries);
+    let [.., restored, _] = states.as_slice() else {
+        return Err(format!("nothing to undo for task '{id}'"));
+    };
+    let mut entry = TaskEntry {
+        v: SCHEMA_VERSION,
+        kind: Some(EntryKind::Undo),
+        ts: now_ts(),
+        ..(*restored).clone()
+    };
+    entry.stamp(origin);
+    lock.append(&entry);
+    Ok(entry)
+}
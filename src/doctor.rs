@@ -0,0 +1,47 @@
+use crate::store::{BadLine, TaskStore};
+use std::fs;
+use std::io::Write as _;
+
+pub struct DoctorReport {
+    pub entries: usize,
+    /// Malformed lines not yet quarantined (moved to the sidecar when repairing).
+    pub bad: Vec<BadLine>,
+    /// Malformed lines quarantined by an earlier run.
+    pub already_quarantined: usize,
+    pub truncated: bool,
+}
+
+/// Scan the log for malformed lines. With `quarantine`, copy them into
+/// `tasks.log.quarantine` so normal commands stop warning about them, and
+/// terminate a truncated final line. `tasks.log` itself is only appended to.
+pub fn run_doctor(store: &TaskStore, quarantine: bool) -> Result<DoctorReport, String> {
+    let mut lock = store.lock()?;
+    let scan = store.scan();
+    let total_bad = scan.bad.len();
+    let bad = store.unquarantined(&scan.bad);
+
+    if quarantine {
+        if !bad.is_empty() {
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(store.quarantine_path())
+                .map_err(|e| format!("failed to open quarantine file: {e}"))?;
+            for line in &bad {
+                let json = serde_json::to_string(line).expect("Failed to serialize BadLine");
+                writeln!(file, "{json}")
+                    .map_err(|e| format!("failed to write quarantine file: {e}"))?;
+            }
+        }
+        if scan.truncated {
+            lock.terminate_partial_line();
+        }
+    }
+
+    Ok(DoctorReport {
+        entries: scan.entries.len(),
+        already_quarantined: total_bad - bad.len(),
+        bad,
+        truncated: scan.truncated,
+    })
+}
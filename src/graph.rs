@@ -0,0 +1,424 @@
+use crate::store::TaskEntry;
+use crate::workflow::Workflow;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Links between tasks over their latest state: dependency edges
+/// (`depends_on`) and the parent/sub-task hierarchy (`parent`).
+pub struct TaskGraph<'a> {
+    tasks: HashMap<&'a str, &'a TaskEntry>,
+    dependents: HashMap<&'a str, Vec<&'a str>>,
+    children: HashMap<&'a str, Vec<&'a str>>,
+    /// Status a task waits in until it is ready (`todo`, or its alias).
+    todo: String,
+    /// Status that satisfies dependencies and counts in roll-ups (`done`, or
+    /// its alias).
+    done: String,
+}
+
+/// How many direct sub-tasks of a parent are `done` (or its workflow alias).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Rollup {
+    pub done: usize,
+    pub total: usize,
+}
+
+impl std::fmt::Display for Rollup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.done, self.total)
+    }
+}
+
+/// A task with its related tasks nested below it, for `task graph` and the
+/// sub-task tree of `task get`.
+#[derive(Serialize)]
+pub struct GraphNode<'a> {
+    pub id: &'a str,
+    pub status: &'a str,
+    pub title: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollup: Option<Rollup>,
+    /// Set when this task already appeared higher up in the same tree.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub repeated: bool,
+    pub children: Vec<GraphNode<'a>>,
+}
+
+impl<'a> TaskGraph<'a> {
+    pub fn new(tasks: &'a [TaskEntry]) -> Self {
+        let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+        let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+        for task in tasks {
+            for dep in &task.depends_on {
+                dependents.entry(dep).or_default().push(&task.id);
+            }
+            if let Some(parent) = &task.parent {
+                children.entry(parent).or_default().push(&task.id);
+            }
+        }
+        Self {
+            tasks: tasks.iter().map(|t| (t.id.as_str(), t)).collect(),
+            dependents,
+            children,
+            todo: "todo".into(),
+            done: "done".into(),
+        }
+    }
+
+    /// Use whatever the workflow aliases `todo` and `done` to.
+    pub fn with_workflow(mut self, workflow: &Workflow) -> Self {
+        self.todo = workflow.canonical("todo");
+        self.done = workflow.canonical("done");
+        self
+    }
+
+    /// Done/total over the direct sub-tasks of `id`, if it has any.
+    pub fn rollup(&self, id: &str) -> Option<Rollup> {
+        let children = self.children.get(id)?;
+        let done = children
+            .iter()
+            .filter(|c| self.tasks.get(*c).is_some_and(|t| t.status == self.done))
+            .count();
+        Some(Rollup {
+            done,
+            total: children.len(),
+        })
+    }
+
+    /// Sub-tasks of `id`, recursively.
+    pub fn subtasks(&self, id: &str) -> Vec<GraphNode<'a>> {
+        self.tree(id, &|g, id| g.children.get(id).cloned().unwrap_or_default())
+    }
+
+    /// Order `tasks` so that each sub-task directly follows its parent, and
+    /// pair each with its depth. Tasks whose parent is not in `tasks` are roots.
+    pub fn nest<'t>(&self, tasks: &'t [TaskEntry]) -> Vec<(usize, &'t TaskEntry)> {
+        let listed: HashSet<&str> = tasks.iter().map(|t| t.id.as_str()).collect();
+        let mut kids: HashMap<&str, Vec<&'t TaskEntry>> = HashMap::new();
+        let mut roots = Vec::new();
+        for task in tasks {
+            match task.parent.as_deref().filter(|p| listed.contains(p)) {
+                Some(parent) => kids.entry(parent).or_default().push(task),
+                None => roots.push(task),
+            }
+        }
+        fn walk<'t>(
+            task: &'t TaskEntry,
+            depth: usize,
+            kids: &HashMap<&str, Vec<&'t TaskEntry>>,
+            out: &mut Vec<(usize, &'t TaskEntry)>,
+        ) {
+            out.push((depth, task));
+            for child in kids.get(task.id.as_str()).into_iter().flatten() {
+                walk(child, depth + 1, kids, out);
+            }
+        }
+        let mut out = Vec::with_capacity(tasks.len());
+        for root in roots {
+            walk(root, 0, &kids, &mut out);
+        }
+        out
+    }
+
+    fn deps_of(&self, id: &str) -> &'a [String] {
+        self.tasks
+            .get(id)
+            .map(|t| t.depends_on.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Unknown dependencies count as unmet.
+    fn dependencies_done(&self, task: &TaskEntry) -> bool {
+        task.depends_on.iter().all(|dep| {
+            self.tasks
+                .get(dep.as_str())
+                .is_some_and(|d| d.status == self.done)
+        })
+    }
+
+    /// Dependencies of `task` that are not `done`, as `id (status)`.
+    pub fn unfinished_dependencies(&self, task: &TaskEntry) -> Vec<String> {
+        task.depends_on
+            .iter()
+            .filter_map(|dep| match self.tasks.get(dep.as_str()) {
+                Some(d) if d.status == self.done => None,
+                Some(d) => Some(format!("{dep} ({})", d.status)),
+                None => Some(format!("{dep} (unknown task)")),
+            })
+            .collect()
+    }
+
+    /// A `todo` task is ready when every task it depends on is `done`.
+    pub fn is_ready(&self, task: &TaskEntry) -> bool {
+        task.status == self.todo && self.dependencies_done(task)
+    }
+
+    /// What `task claim --next` may pick: ready tasks, plus `doing` tasks whose
+    /// lease expired and so went back to the pool.
+    pub fn is_claimable(&self, task: &TaskEntry, now: DateTime<Local>) -> bool {
+        self.is_ready(task) || (task.lease_expired(now) && self.dependencies_done(task))
+    }
+
+    /// If making `from` depend on `to` would close a cycle, return it as
+    /// `[from, to, ..., from]`.
+    pub fn cycle_if_added(&self, from: &str, to: &str) -> Option<Vec<String>> {
+        let mut stack = vec![vec![to.to_string()]];
+        let mut seen = HashSet::new();
+        while let Some(path) = stack.pop() {
+            let last = path.last().unwrap();
+            if last == from {
+                let mut cycle = vec![from.to_string()];
+                cycle.extend(path);
+                return Some(cycle);
+            }
+            if !seen.insert(last.clone()) {
+                continue;
+            }
+            for dep in self.deps_of(last) {
+                let mut next = path.clone();
+                next.push(dep.clone());
+                stack.push(next);
+            }
+        }
+        None
+    }
+
+    /// If making `parent` the parent of `id` would close a loop, return it as
+    /// `[id, parent, ..., id]`.
+    pub fn parent_cycle_if_set(&self, id: &str, parent: &str) -> Option<Vec<String>> {
+        let mut path = vec![id.to_string()];
+        let mut current = Some(parent);
+        while let Some(ancestor) = current {
+            if path[1..].iter().any(|p| p == ancestor) {
+                return None;
+            }
+            path.push(ancestor.to_string());
+            if ancestor == id {
+                return Some(path);
+            }
+            current = self.tasks.get(ancestor).and_then(|t| t.parent.as_deref());
+        }
+        None
+    }
+
+    /// Tasks `id` depends on, transitively.
+    pub fn upstream(&self, id: &str) -> Vec<GraphNode<'a>> {
+        self.tree(id, &|g, id| {
+            g.deps_of(id).iter().map(String::as_str).collect()
+        })
+    }
+
+    /// Tasks that depend on `id`, transitively.
+    pub fn downstream(&self, id: &str) -> Vec<GraphNode<'a>> {
+        self.tree(id, &|g, id| {
+            g.dependents.get(id).cloned().unwrap_or_default()
+        })
+    }
+
+    fn tree(&self, root: &str, next: &dyn Fn(&Self, &str) -> Vec<&'a str>) -> Vec<GraphNode<'a>> {
+        let mut path = vec![root.to_string()];
+        self.subtree(root, next, &mut path)
+    }
+
+    fn subtree(
+        &self,
+        id: &str,
+        next: &dyn Fn(&Self, &str) -> Vec<&'a str>,
+        path: &mut Vec<String>,
+    ) -> Vec<GraphNode<'a>> {
+        let mut nodes = Vec::new();
+        for child in next(self, id) {
+            let Some(task) = self.tasks.get(child) else {
+                nodes.push(GraphNode {
+                    id: child,
+                    status: "?",
+                    title: "(unknown task)",
+                    rollup: None,
+                    repeated: false,
+                    children: vec![],
+                });
+                continue;
+            };
+            let repeated = path.iter().any(|p| p == child);
+            let children = if repeated {
+                vec![]
+            } else {
+                path.push(child.to_string());
+                let children = self.subtree(child, next, path);
+                path.pop();
+                children
+            };
+            nodes.push(GraphNode {
+                id: &task.id,
+                status: &task.status,
+                title: &task.title,
+                rollup: self.rollup(&task.id),
+                repeated,
+                children,
+            });
+        }
+        nodes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: &str, status: &str, deps: &[&str]) -> TaskEntry {
+        TaskEntry {
+            id: id.into(),
+            status: status.into(),
+            title: id.to_uppercase(),
+            depends_on: deps.iter().map(|d| d.to_string()).collect(),
+            ..TaskEntry::default()
+        }
+    }
+
+    fn child(id: &str, status: &str, parent: &str) -> TaskEntry {
+        TaskEntry {
+            parent: Some(parent.into()),
+            ..task(id, status, &[])
+        }
+    }
+
+    fn ids(nodes: &[GraphNode]) -> Vec<String> {
+        nodes.iter().map(|n| n.id.to_string()).collect()
+    }
+
+    #[test]
+    fn ready_requires_todo_and_done_dependencies() {
+        let tasks = vec![
+            task("a", "done", &[]),
+            task("b", "doing", &[]),
+            task("c", "todo", &["a"]),
+            task("d", "todo", &["a", "b"]),
+            task("e", "todo", &[]),
+            task("f", "doing", &["a"]),
+            task("g", "todo", &["missing"]),
+        ];
+        let graph = TaskGraph::new(&tasks);
+        let ready: Vec<&str> = tasks
+            .iter()
+            .filter(|t| graph.is_ready(t))
+            .map(|t| t.id.as_str())
+            .collect();
+        assert_eq!(ready, vec!["c", "e"]);
+        assert_eq!(graph.unfinished_dependencies(&tasks[3]), vec!["b (doing)"]);
+        assert_eq!(
+            graph.unfinished_dependencies(&tasks[6]),
+            vec!["missing (unknown task)"]
+        );
+    }
+
+    #[test]
+    fn workflow_aliases_rename_todo_and_done() {
+        let workflow: Workflow =
+            toml::from_str("[aliases]\ntodo = \"open\"\ndone = \"closed\"\n").unwrap();
+        let tasks = vec![
+            task("a", "closed", &[]),
+            task("b", "open", &["a"]),
+            child("c", "closed", "b"),
+        ];
+        let graph = TaskGraph::new(&tasks).with_workflow(&workflow);
+        assert!(graph.is_ready(&tasks[1]));
+        assert_eq!(graph.rollup("b"), Some(Rollup { done: 1, total: 1 }));
+        assert!(!TaskGraph::new(&tasks).is_ready(&tasks[1]));
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let tasks = vec![
+            task("a", "todo", &["b"]),
+            task("b", "todo", &["c"]),
+            task("c", "todo", &[]),
+        ];
+        let graph = TaskGraph::new(&tasks);
+        assert_eq!(
+            graph.cycle_if_added("c", "a").unwrap(),
+            vec!["c", "a", "b", "c"]
+        );
+        assert_eq!(graph.cycle_if_added("a", "a").unwrap(), vec!["a", "a"]);
+        assert!(graph.cycle_if_added("a", "c").is_none());
+    }
+
+    #[test]
+    fn detects_parent_cycles() {
+        let tasks = vec![
+            task("epic", "todo", &[]),
+            child("a", "todo", "epic"),
+            child("a1", "todo", "a"),
+        ];
+        let graph = TaskGraph::new(&tasks);
+        assert_eq!(
+            graph.parent_cycle_if_set("epic", "a1").unwrap(),
+            vec!["epic", "a1", "a", "epic"]
+        );
+        assert_eq!(graph.parent_cycle_if_set("a", "a").unwrap(), vec!["a", "a"]);
+        assert!(graph.parent_cycle_if_set("a1", "epic").is_none());
+    }
+
+    #[test]
+    fn upstream_and_downstream_trees() {
+        let tasks = vec![
+            task("a", "done", &[]),
+            task("b", "todo", &["a"]),
+            task("c", "todo", &["b", "a"]),
+        ];
+        let graph = TaskGraph::new(&tasks);
+        let up = graph.upstream("c");
+        assert_eq!(ids(&up), vec!["b", "a"]);
+        assert_eq!(ids(&up[0].children), vec!["a"]);
+        let down = graph.downstream("a");
+        assert_eq!(ids(&down), vec!["b", "c"]);
+        assert_eq!(ids(&down[0].children), vec!["c"]);
+    }
+
+    #[test]
+    fn rollup_counts_direct_children() {
+        let tasks = vec![
+            task("epic", "doing", &[]),
+            child("a", "done", "epic"),
+            child("b", "todo", "epic"),
+            child("c", "done", "epic"),
+            child("c1", "todo", "c"),
+        ];
+        let graph = TaskGraph::new(&tasks);
+        assert_eq!(graph.rollup("epic"), Some(Rollup { done: 2, total: 3 }));
+        assert_eq!(graph.rollup("epic").unwrap().to_string(), "2/3");
+        assert_eq!(graph.rollup("a"), None);
+        let subtasks = graph.subtasks("epic");
+        assert_eq!(ids(&subtasks), vec!["a", "b", "c"]);
+        assert_eq!(ids(&subtasks[2].children), vec!["c1"]);
+    }
+
+    #[test]
+    fn nest_places_children_under_parents() {
+        let tasks = vec![
+            child("a1", "todo", "a"),
+            task("a", "todo", &[]),
+            task("b", "todo", &[]),
+            child("a2", "todo", "a"),
+            child("a1x", "todo", "a1"),
+            child("orphan", "todo", "elsewhere"),
+        ];
+        let graph = TaskGraph::new(&tasks);
+        let rows: Vec<(usize, &str)> = graph
+            .nest(&tasks)
+            .into_iter()
+            .map(|(d, t)| (d, t.id.as_str()))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (0, "a"),
+                (1, "a1"),
+                (2, "a1x"),
+                (1, "a2"),
+                (0, "b"),
+                (0, "orphan"),
+            ]
+        );
+    }
+}
@@ -1,13 +1,43 @@
+mod actor;
+mod archive;
+mod claim;
+mod compact;
+mod config;
+mod delete;
+mod doctor;
+mod graph;
 mod init;
+mod output;
 mod project;
+mod search;
+mod snapshot;
+mod stale;
 mod store;
+mod time;
+mod workflow;
 
-use clap::{Parser, Subcommand};
-use store::{TaskEntry, TaskStore, gen_id};
+use chrono::{DateTime, FixedOffset};
+use clap::{Parser, Subcommand, ValueEnum};
+use graph::TaskGraph;
+use output::Format;
+use snapshot::Snapshot;
+use std::cell::LazyCell;
+use std::io::Read as _;
+use store::{IdFormat, Origin, TaskEntry, TaskStore};
 
 #[derive(Parser)]
 #[command(name = "task", about = "Lightweight task management for coding agents")]
 struct Cli {
+    /// Output format for create, update, list and get
+    #[arg(long, global = true, value_enum, default_value_t = Format::Table)]
+    format: Format,
+    /// Shorthand for `--format json`
+    #[arg(long, global = true, conflicts_with = "format")]
+    json: bool,
+    /// Who is writing (default: TASK_ACTOR, a detected agent, or human).
+    /// With list, only tasks last written by this actor
+    #[arg(long, global = true, value_name = "NAME")]
+    actor: Option<String>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -20,13 +50,28 @@ enum Commands {
         title: String,
         /// Task description
         description: Option<String>,
-        /// Initial status (default: todo)
-        #[arg(long, default_value = "todo")]
-        status: String,
+        /// Initial status (default: `default_status` from config, else todo)
+        #[arg(long)]
+        status: Option<String>,
+        /// Tag / label (repeatable)
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Priority, p0 (highest) to p9
+        #[arg(long, value_parser = store::parse_priority)]
+        priority: Option<u8>,
+        /// Task that must be done first (repeatable)
+        #[arg(long = "depends-on", value_name = "ID")]
+        depends_on: Vec<String>,
+        /// Parent task (epic) this is a sub-task of
+        #[arg(long, value_name = "ID")]
+        parent: Option<String>,
+        /// Allow a status the configured workflow does not know
+        #[arg(long)]
+        force: bool,
     },
     /// Update task status
     Update {
-        /// Task ID (8-char hex)
+        /// Task ID or unique ID prefix
         id: String,
         /// New status
         status: String,
@@ -35,20 +80,222 @@ enum Commands {
         /// Update description
         #[arg(long)]
         description: Option<String>,
+        /// Add a tag (repeatable)
+        #[arg(long = "add-tag", value_name = "TAG")]
+        add_tags: Vec<String>,
+        /// Remove a tag (repeatable)
+        #[arg(long = "remove-tag", value_name = "TAG")]
+        remove_tags: Vec<String>,
+        /// Set priority, p0 (highest) to p9
+        #[arg(long, value_parser = store::parse_priority)]
+        priority: Option<u8>,
+        /// Add a task that must be done first (repeatable)
+        #[arg(long = "depends-on", value_name = "ID")]
+        depends_on: Vec<String>,
+        /// Skip the configured workflow's status and transition checks
+        #[arg(long)]
+        force: bool,
+    },
+    /// Leave a comment on a task without changing its status
+    Comment {
+        /// Task ID or unique ID prefix
+        id: String,
+        /// Comment text
+        #[arg(required_unless_present = "stdin", conflicts_with = "stdin")]
+        text: Option<String>,
+        /// Read the comment text from stdin
+        #[arg(long)]
+        stdin: bool,
     },
-    /// List tasks
+    /// Correct a task's fields without changing its status
+    Edit {
+        /// Task ID or unique ID prefix
+        id: String,
+        /// New title
+        #[arg(long)]
+        title: Option<String>,
+        /// New description
+        #[arg(long)]
+        description: Option<String>,
+        /// Move the task to another project
+        #[arg(long)]
+        project: Option<String>,
+        /// New priority, p0 (highest) to p9
+        #[arg(long, value_parser = store::parse_priority)]
+        priority: Option<u8>,
+        /// New parent task (epic)
+        #[arg(long, value_name = "ID")]
+        parent: Option<String>,
+    },
+    /// List tasks, highest priority first, then oldest first
     List {
         /// Filter by status
         status: Option<String>,
+        /// Only these statuses (repeatable)
+        #[arg(long = "status", value_name = "STATUS")]
+        statuses: Vec<String>,
+        /// Hide these statuses (repeatable)
+        #[arg(long = "not-status", value_name = "STATUS")]
+        not_statuses: Vec<String>,
+        /// Only tasks created at or after this time (24h, 7d, 2026-02-22, RFC 3339)
+        #[arg(long, value_parser = time::parse_time)]
+        since: Option<DateTime<FixedOffset>>,
+        /// Only tasks created before this time
+        #[arg(long, value_parser = time::parse_time)]
+        until: Option<DateTime<FixedOffset>>,
+        /// Only tasks with an entry in this period (e.g. 24h)
+        #[arg(long, value_parser = time::parse_duration)]
+        updated_within: Option<chrono::Duration>,
+        /// Also show tasks in a terminal status (default: done)
+        #[arg(long)]
+        include_done: bool,
+        /// Also show archived tasks
+        #[arg(long)]
+        include_archived: bool,
+        /// Sort order (default: priority, then oldest first)
+        #[arg(long, value_enum)]
+        sort: Option<SortKey>,
+        /// Show all projects (default: current project only)
+        #[arg(long)]
+        all: bool,
+        /// Only tasks carrying this tag (repeatable; all must match)
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Only todo tasks whose dependencies are all done
+        #[arg(long)]
+        ready: bool,
+        /// Indent sub-tasks under their parent
+        #[arg(long)]
+        tree: bool,
+        /// Table columns, overriding the `columns` setting (e.g. id,status,branch,title)
+        #[arg(long, value_delimiter = ',', value_name = "COLUMNS")]
+        columns: Vec<output::Column>,
+    },
+    /// Atomically claim a task for an agent and move it to doing
+    Claim {
+        /// Task ID or unique ID prefix
+        #[arg(required_unless_present = "next", conflicts_with = "next")]
+        id: Option<String>,
+        /// Claim the highest-priority ready todo task instead
+        #[arg(long)]
+        next: bool,
+        /// Name of the claiming agent (default: the actor)
+        #[arg(long)]
+        agent: Option<String>,
+        /// Lease length; after it the task returns to the pool (e.g. 30m, 2h)
+        #[arg(long, default_value = "30m", value_parser = time::parse_duration)]
+        ttl: chrono::Duration,
+        /// With --next, pick from all projects
+        #[arg(long)]
+        all: bool,
+        /// Claim the task even if it is finished or its dependencies are not
+        #[arg(long)]
+        force: bool,
+    },
+    /// Record that the agent working on a task is still alive
+    Heartbeat {
+        /// Task ID or unique ID prefix
+        id: String,
+    },
+    /// List doing tasks with no recent activity (log entry or heartbeat)
+    Stale {
+        /// Idle time after which a doing task counts as stale
+        #[arg(long, default_value = "2h", value_parser = time::parse_duration)]
+        older_than: chrono::Duration,
+        /// Move stale tasks to blocked with a generated note
+        #[arg(long)]
+        block: bool,
         /// Show all projects (default: current project only)
         #[arg(long)]
         all: bool,
     },
+    /// Search titles, descriptions and notes across every log entry
+    Search {
+        /// Text to look for (a regular expression with --regex)
+        query: String,
+        /// Treat the query as a regular expression
+        #[arg(long)]
+        regex: bool,
+        /// Match case exactly (default: ignore case)
+        #[arg(short = 's', long)]
+        case_sensitive: bool,
+        /// Only tasks whose current status is this (repeatable)
+        #[arg(long)]
+        status: Vec<String>,
+        /// Search all projects (default: current project only)
+        #[arg(long)]
+        all: bool,
+        /// Also search archived tasks
+        #[arg(long)]
+        include_archived: bool,
+        /// Highlight matches
+        #[arg(long, value_enum, default_value_t = output::ColorChoice::Auto)]
+        color: output::ColorChoice,
+    },
+    /// Archive tasks: hidden from default views, kept in the log
+    Archive {
+        /// Task IDs or unique ID prefixes
+        #[arg(
+            required_unless_present = "done_before",
+            conflicts_with = "done_before"
+        )]
+        ids: Vec<String>,
+        /// Archive tasks in a terminal status last updated longer ago than this (e.g. 30d)
+        #[arg(long, value_parser = time::parse_duration)]
+        done_before: Option<chrono::Duration>,
+        /// With --done-before, archive in all projects
+        #[arg(long)]
+        all: bool,
+    },
+    /// Hide tasks from every view by appending a tombstone
+    Delete {
+        /// Task IDs or unique ID prefixes
+        #[arg(required = true)]
+        ids: Vec<String>,
+        /// Delete even if other tasks depend on or are sub-tasks of them
+        #[arg(long)]
+        force: bool,
+    },
+    /// Restore the state before a task's last change (repeat to go further back)
+    Undo {
+        /// Task ID or unique ID prefix
+        id: String,
+    },
+    /// Move the history of long-finished tasks into monthly segment files
+    Compact {
+        /// Only tasks done or archived with no entry for this long (e.g. 30d)
+        #[arg(long, default_value = "30d", value_parser = time::parse_duration)]
+        older_than: chrono::Duration,
+        /// Show what would be moved without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
     /// Show task detail and state transition history
     Get {
-        /// Task ID (8-char hex)
+        /// Task ID or unique ID prefix
         id: String,
     },
+    /// Show the tasks a task depends on (upstream) and that depend on it (downstream)
+    Graph {
+        /// Task ID or unique ID prefix
+        id: String,
+    },
+    /// Check tasks.log for malformed or truncated lines
+    Doctor {
+        /// Copy bad lines into tasks.log.quarantine and terminate a truncated final line
+        #[arg(long)]
+        quarantine: bool,
+    },
+    /// Show or change settings in the global config and the project .task.toml
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Show the project identity and map old project values onto it
+    Project {
+        #[command(subcommand)]
+        action: ProjectAction,
+    },
     /// Inject instruction snippet into agent config files
     Init {
         /// Inject into global config files instead of project-local
@@ -57,100 +304,666 @@ enum Commands {
     },
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum SortKey {
+    /// Oldest first
+    Created,
+    /// Most recently updated first
+    Updated,
+    /// In workflow order if one is configured, else alphabetically
+    Status,
+    Project,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Show every key with its effective value and where it came from
+    List,
+    /// Print the effective value of a key
+    Get {
+        /// Config key
+        key: String,
+    },
+    /// Set a key in the nearest .task.toml (created at the project root if
+    /// missing); id_format is stored next to the log instead
+    Set {
+        /// Config key
+        key: String,
+        /// New value (comma-separated for columns)
+        value: String,
+        /// Write to the global config instead
+        #[arg(long)]
+        global: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProjectAction {
+    /// Show the current project and the recorded aliases
+    Show,
+    /// Show tasks recorded under OLD as belonging to another project
+    Alias {
+        /// Project value as recorded in the log (e.g. an old checkout path)
+        old: String,
+        /// Canonical project name (default: the current project)
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// Alias recorded path-based projects to the identity their directory now resolves to
+    Migrate {
+        /// Only print what would be aliased
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("Error: {msg}");
+    std::process::exit(1);
+}
+
+fn resolve<'a>(snapshot: &'a Snapshot, prefix: &str) -> &'a TaskEntry {
+    snapshot.resolve(prefix).unwrap_or_else(|e| fail(&e))
+}
+
+/// Resolve dependency IDs and add them to `entry`, refusing unknown tasks and
+/// edges that would create a cycle.
+fn add_dependencies(snapshot: &Snapshot, entry: &mut TaskEntry, deps: Vec<String>) {
+    if deps.is_empty() {
+        return;
+    }
+    let mut tasks = snapshot.live_tasks();
+    for dep in deps {
+        let dep = resolve(snapshot, &dep).id.clone();
+        if entry.depends_on.contains(&dep) {
+            continue;
+        }
+        if let Some(cycle) = TaskGraph::new(&tasks).cycle_if_added(&entry.id, &dep) {
+            fail(&format!("dependency cycle: {}", cycle.join(" -> ")));
+        }
+        entry.depends_on.push(dep);
+        match tasks.iter_mut().find(|t| t.id == entry.id) {
+            Some(task) => task.depends_on = entry.depends_on.clone(),
+            None => tasks.push(entry.clone()),
+        }
+    }
+}
+
 fn main() {
     let cli = Cli::parse();
-    let store = TaskStore::default_path();
-    let project = project::get_project();
+    let format = if cli.json { Format::Json } else { cli.format };
+    let mut config = config::load().unwrap_or_else(|e| fail(&e));
+    let store = TaskStore::open(config.log_path.clone());
+    if let Ok(Some(id_format)) = store.id_format() {
+        config.apply_id_format(id_format, store.sidecar("id_format"));
+    }
+    let project = config.project.clone().unwrap_or_else(project::get_project);
+    // Looking up the worktree and branch runs git, so only commands that
+    // append entries pay for it.
+    let origin = LazyCell::new(|| Origin {
+        worktree: project::worktree(),
+        branch: project::branch(),
+        actor: Some(actor::actor(cli.actor.clone())),
+        session: actor::session(),
+    });
+    let workflow = &config.workflow;
 
     match cli.command {
         Commands::Create {
             title,
             description,
             status,
+            tags,
+            priority,
+            depends_on,
+            parent,
+            force,
         } => {
-            let id = gen_id();
-            store.append(&TaskEntry::new(
+            let status = status.unwrap_or_else(|| config.default_status().to_string());
+            let status = if force {
+                workflow.canonical(&status)
+            } else {
+                workflow
+                    .resolve_status(&status)
+                    .unwrap_or_else(|e| fail(&e))
+            };
+            let mut lock = store.lock().unwrap_or_else(|e| fail(&e));
+            let id = store.unused_id().unwrap_or_else(|e| fail(&e));
+            let mut entry = TaskEntry::new(
                 id.clone(),
                 project,
                 status,
                 title,
                 description.unwrap_or_default(),
                 String::new(),
-            ));
-            println!("task created! ID: {id}");
-            println!("TASK_ADD_{id}");
+            );
+            entry.add_tags(tags);
+            entry.priority = priority;
+            let snapshot = store.snapshot();
+            entry.parent = parent.map(|p| resolve(&snapshot, &p).id.clone());
+            add_dependencies(&snapshot, &mut entry, depends_on);
+            entry.stamp(&origin);
+            lock.append(&entry);
+            drop(lock);
+            if format == Format::Table {
+                println!("task created! ID: {id}");
+                println!("TASK_ADD_{id}");
+            } else {
+                output::print_entry(format, &entry);
+            }
         }
         Commands::Update {
             id,
             status,
             note,
             description,
+            add_tags,
+            remove_tags,
+            priority,
+            depends_on,
+            force,
         } => {
-            if !store.id_exists(&id) {
-                eprintln!("Error: task '{id}' not found");
-                std::process::exit(1);
+            let status = if force {
+                workflow.canonical(&status)
+            } else {
+                workflow
+                    .resolve_status(&status)
+                    .unwrap_or_else(|e| fail(&e))
+            };
+            let mut lock = store.lock().unwrap_or_else(|e| fail(&e));
+            let snapshot = store.snapshot();
+            let prev = resolve(&snapshot, &id);
+            let id = prev.id.clone();
+            if !force {
+                workflow
+                    .check_transition(&prev.status, &status)
+                    .unwrap_or_else(|e| fail(&e));
             }
-            let prev = store.latest_entry(&id).unwrap();
-            let new_description = description.unwrap_or(prev.description);
-            store.append(&TaskEntry::new(
-                id.clone(),
-                project,
-                status.clone(),
-                prev.title,
-                new_description,
-                note.unwrap_or_default(),
-            ));
-            println!("TASK_{}_{id}", status.to_uppercase());
-        }
-        Commands::List { status, all } => {
+            let mut entry = prev.successor(status.clone(), note.unwrap_or_default());
+            if let Some(description) = description {
+                entry.description = description;
+            }
+            entry.remove_tags(&remove_tags);
+            entry.add_tags(add_tags);
+            if priority.is_some() {
+                entry.priority = priority;
+            }
+            add_dependencies(&snapshot, &mut entry, depends_on);
+            if entry.status != workflow.canonical("doing") {
+                entry.release();
+            }
+            entry.stamp(&origin);
+            lock.append(&entry);
+            drop(lock);
+            if format == Format::Table {
+                println!("TASK_{}_{id}", status.to_uppercase());
+            } else {
+                output::print_entry(format, &entry);
+            }
+        }
+        Commands::Comment { id, text, .. } => {
+            let text = match text {
+                Some(text) => text,
+                None => {
+                    let mut text = String::new();
+                    std::io::stdin()
+                        .read_to_string(&mut text)
+                        .unwrap_or_else(|e| fail(&format!("failed to read stdin: {e}")));
+                    text.trim_end().to_string()
+                }
+            };
+            if text.trim().is_empty() {
+                fail("comment must not be empty");
+            }
+            let mut lock = store.lock().unwrap_or_else(|e| fail(&e));
+            let snapshot = store.snapshot();
+            let prev = resolve(&snapshot, &id);
+            let id = prev.id.clone();
+            let mut entry = prev.comment(text);
+            entry.stamp(&origin);
+            lock.append(&entry);
+            drop(lock);
+            if format == Format::Table {
+                println!("TASK_COMMENTED_{id}");
+            } else {
+                output::print_entry(format, &entry);
+            }
+        }
+        Commands::Edit {
+            id,
+            title,
+            description,
+            project: new_project,
+            priority,
+            parent,
+        } => {
+            if title.is_none()
+                && description.is_none()
+                && new_project.is_none()
+                && priority.is_none()
+                && parent.is_none()
+            {
+                fail(
+                    "nothing to edit (use --title, --description, --project, --priority or --parent)",
+                );
+            }
+            let mut lock = store.lock().unwrap_or_else(|e| fail(&e));
+            let snapshot = store.snapshot();
+            let prev = resolve(&snapshot, &id);
+            let id = prev.id.clone();
+            let mut entry = prev.edited();
+            if let Some(title) = title {
+                if title.trim().is_empty() {
+                    fail("title must not be empty");
+                }
+                entry.title = title;
+            }
+            if let Some(description) = description {
+                entry.description = description;
+            }
+            if let Some(new_project) = new_project {
+                entry.project = store::canonical_project(&store.project_aliases(), &new_project);
+            }
+            if priority.is_some() {
+                entry.priority = priority;
+            }
+            if let Some(parent) = parent {
+                let parent = resolve(&snapshot, &parent).id.clone();
+                let tasks = snapshot.live_tasks();
+                if let Some(cycle) = TaskGraph::new(&tasks).parent_cycle_if_set(&id, &parent) {
+                    fail(&format!("parent cycle: {}", cycle.join(" -> ")));
+                }
+                entry.parent = Some(parent);
+            }
+            entry.stamp(&origin);
+            lock.append(&entry);
+            drop(lock);
+            if format == Format::Table {
+                println!("TASK_EDITED_{id}");
+            } else {
+                output::print_entry(format, &entry);
+            }
+        }
+        Commands::List {
+            status,
+            statuses,
+            not_statuses,
+            since,
+            until,
+            updated_within,
+            include_done,
+            include_archived,
+            sort,
+            all,
+            tags,
+            ready,
+            tree,
+            columns,
+        } => {
             let project_filter = if all { None } else { Some(project.as_str()) };
-            let tasks = store.current_tasks(project_filter, status.as_deref());
-            if tasks.is_empty() {
-                return;
+            let statuses: Vec<String> = status
+                .into_iter()
+                .chain(statuses)
+                .map(|s| workflow.canonical(&s))
+                .collect();
+            let not_statuses: Vec<String> =
+                not_statuses.iter().map(|s| workflow.canonical(s)).collect();
+            // One snapshot load serves the filtered rows, the whole-log graph
+            // and creation times.
+            let snapshot = store.snapshot();
+            let everything: Vec<TaskEntry> =
+                snapshot.tasks.into_iter().filter(|t| !t.deleted).collect();
+            let mut tasks: Vec<TaskEntry> = everything
+                .iter()
+                .filter(|t| project_filter.is_none_or(|p| t.project == p))
+                .cloned()
+                .collect();
+            tasks.retain(|t| statuses.is_empty() || statuses.contains(&t.status));
+            tasks.retain(|t| !not_statuses.contains(&t.status));
+            // Asking for statuses by name shows them even if terminal.
+            if !include_done && statuses.is_empty() {
+                let terminal = config.terminal_statuses();
+                tasks.retain(|t| !terminal.contains(&t.status));
+            }
+            if !include_archived {
+                tasks.retain(|t| !t.archived);
+            }
+            let created = snapshot.created;
+            let created_at = |t: &TaskEntry| created.get(&t.id).and_then(|ts| time::parse_ts(ts));
+            if since.is_some() || until.is_some() {
+                tasks.retain(|t| {
+                    created_at(t).is_some_and(|c| {
+                        since.is_none_or(|s| c >= s) && until.is_none_or(|u| c < u)
+                    })
+                });
+            }
+            if let Some(within) = updated_within {
+                let cutoff = time::before_now(within).unwrap_or_else(|e| fail(&e));
+                tasks.retain(|t| time::parse_ts(&t.ts).is_some_and(|ts| ts >= cutoff));
             }
-            println!("{:<10} {:<8} {:<24} TITLE", "ID", "STATUS", "PROJECT");
-            for task in tasks {
+            let graph = TaskGraph::new(&everything).with_workflow(workflow);
+            tasks.retain(|t| t.has_tags(&tags));
+            if let Some(actor) = &cli.actor {
+                tasks.retain(|t| t.actor.as_ref() == Some(actor));
+            }
+            if ready {
+                tasks.retain(|t| graph.is_ready(t));
+            }
+            tasks.sort_by_key(TaskEntry::priority_rank);
+            match sort {
+                None => {}
+                Some(SortKey::Created) => tasks.sort_by_key(|t| created_at(t)),
+                Some(SortKey::Updated) => {
+                    tasks.sort_by_key(|t| std::cmp::Reverse(time::parse_ts(&t.ts)))
+                }
+                Some(SortKey::Status) => tasks.sort_by_cached_key(|t| {
+                    let rank = workflow.statuses.iter().position(|s| *s == t.status);
+                    (rank.unwrap_or(usize::MAX), t.status.clone())
+                }),
+                Some(SortKey::Project) => tasks.sort_by(|a, b| a.project.cmp(&b.project)),
+            }
+            let columns = if columns.is_empty() {
+                config.columns.as_deref()
+            } else {
+                Some(columns.as_slice())
+            };
+            output::print_list(format, &tasks, &graph, tree, columns);
+        }
+        Commands::Claim {
+            id,
+            next: _,
+            agent,
+            ttl,
+            all,
+            force,
+        } => {
+            let agent = agent.or_else(|| origin.actor.clone()).unwrap_or_default();
+            let terminal = config.terminal_statuses();
+            let request = claim::ClaimRequest {
+                agent: &agent,
+                ttl,
+                project: &project,
+                all,
+                terminal: &terminal,
+                workflow,
+                force,
+                origin: &origin,
+            };
+            let entry = claim::claim(&store, id.as_deref(), &request).unwrap_or_else(|e| fail(&e));
+            if format == Format::Table {
                 println!(
-                    "{:<10} {:<8} {:<24} {}",
-                    task.id, task.status, project::short_project(&task.project), task.title
+                    "task claimed! ID: {} (lease until {})",
+                    entry.id,
+                    entry.lease_until.as_deref().unwrap_or_default()
+                );
+                println!("TASK_{}_{}", entry.status.to_uppercase(), entry.id);
+            } else {
+                output::print_entry(format, &entry);
+            }
+        }
+        Commands::Heartbeat { id } => {
+            let id = store.resolve_id(&id).unwrap_or_else(|e| fail(&e));
+            let ts = time::now_ts();
+            store
+                .record_heartbeat(&id, &ts)
+                .unwrap_or_else(|e| fail(&e));
+            output::print_heartbeat(format, &id, &ts);
+        }
+        Commands::Stale {
+            older_than,
+            block,
+            all,
+        } => {
+            let project_filter = if all { None } else { Some(project.as_str()) };
+            if block {
+                let blocked =
+                    stale::block_stale(&store, project_filter, workflow, older_than, &origin)
+                        .unwrap_or_else(|e| fail(&e));
+                for entry in &blocked {
+                    if format == Format::Table {
+                        println!("TASK_{}_{}", entry.status.to_uppercase(), entry.id);
+                    } else {
+                        output::print_entry(format, entry);
+                    }
+                }
+            } else {
+                let stale = stale::find_stale(
+                    &store,
+                    project_filter,
+                    &workflow.canonical("doing"),
+                    older_than,
+                    chrono::Local::now(),
                 );
+                output::print_stale(format, &stale);
             }
         }
+        Commands::Archive {
+            ids,
+            done_before,
+            all,
+        } => {
+            let archived = match done_before {
+                Some(older_than) => {
+                    let project_filter = if all { None } else { Some(project.as_str()) };
+                    let terminal = config.terminal_statuses();
+                    archive::archive_done_before(
+                        &store,
+                        project_filter,
+                        &terminal,
+                        older_than,
+                        &origin,
+                    )
+                }
+                None => archive::archive(&store, &ids, &origin),
+            }
+            .unwrap_or_else(|e| fail(&e));
+            for entry in &archived {
+                if format == Format::Table {
+                    println!("TASK_ARCHIVED_{}", entry.id);
+                } else {
+                    output::print_entry(format, entry);
+                }
+            }
+        }
+        Commands::Search {
+            query,
+            regex,
+            case_sensitive,
+            status,
+            all,
+            include_archived,
+            color,
+        } => {
+            let re = search::matcher(&query, regex, case_sensitive).unwrap_or_else(|e| fail(&e));
+            let status: Vec<String> = status.iter().map(|s| workflow.canonical(s)).collect();
+            let project_filter = if all { None } else { Some(project.as_str()) };
+            let mut entries = store.read_entries();
+            let compacted = store.read_segments();
+            let mut tasks = store.current_tasks(project_filter, None);
+            tasks.extend(
+                store::latest_states(&compacted)
+                    .into_iter()
+                    .filter(|t| !t.deleted && project_filter.is_none_or(|p| t.project == p)),
+            );
+            tasks.retain(|t| status.is_empty() || status.contains(&t.status));
+            tasks.retain(|t| include_archived || !t.archived);
+            entries.extend(compacted);
+            let results = search::search(&re, &entries, &tasks);
+            output::print_search(format, &results, color.enabled());
+        }
+        Commands::Delete { ids, force } => {
+            let deleted = delete::delete(&store, &ids, force, &origin).unwrap_or_else(|e| fail(&e));
+            for entry in &deleted {
+                if format == Format::Table {
+                    println!("TASK_DELETED_{}", entry.id);
+                } else {
+                    output::print_entry(format, entry);
+                }
+            }
+        }
+        Commands::Undo { id } => {
+            let entry = delete::undo(&store, &id, &origin).unwrap_or_else(|e| fail(&e));
+            if format == Format::Table {
+                println!("TASK_UNDONE_{}", entry.id);
+            } else {
+                output::print_entry(format, &entry);
+            }
+        }
+        Commands::Compact {
+            older_than,
+            dry_run,
+        } => {
+            let terminal = config.terminal_statuses();
+            let report = compact::compact(&store, &terminal, older_than, dry_run)
+                .unwrap_or_else(|e| fail(&e));
+            output::print_compact(format, &report, dry_run);
+        }
         Commands::Get { id } => {
+            let id = store
+                .resolve_any_id(&id)
+                .or_else(|e| store.resolve_compacted_id(&id).map_err(|_| e))
+                .unwrap_or_else(|e| fail(&e));
             let entries = store.entries_for_id(&id);
             if entries.is_empty() {
-                eprintln!("Error: task '{id}' not found");
+                fail(&format!("task '{id}' not found"));
+            }
+            let tasks = store.current_tasks(None, None);
+            let graph = TaskGraph::new(&tasks).with_workflow(workflow);
+            output::print_detail(format, &entries, &graph.subtasks(&id));
+        }
+        Commands::Graph { id } => {
+            let id = store.resolve_id(&id).unwrap_or_else(|e| fail(&e));
+            let tasks = store.current_tasks(None, None);
+            let graph = TaskGraph::new(&tasks).with_workflow(workflow);
+            let task = tasks.iter().find(|t| t.id == id).unwrap();
+            output::print_graph(format, task, &graph.upstream(&id), &graph.downstream(&id));
+        }
+        Commands::Doctor { quarantine } => {
+            let report = doctor::run_doctor(&store, quarantine).unwrap_or_else(|e| fail(&e));
+            let last_line = report.bad.last().map(|b| b.line);
+            for bad in &report.bad {
+                let truncated = if report.truncated && Some(bad.line) == last_line {
+                    " (truncated final line)"
+                } else {
+                    ""
+                };
+                println!("line {}: {}{truncated}", bad.line, bad.error);
+            }
+            if report.already_quarantined > 0 {
+                println!(
+                    "{} line(s) already quarantined in {}",
+                    report.already_quarantined,
+                    store.quarantine_path().display()
+                );
+            }
+            if report.bad.is_empty() {
+                println!("OK: {} entries", report.entries);
+            } else if quarantine {
+                println!(
+                    "Quarantined {} line(s) into {}",
+                    report.bad.len(),
+                    store.quarantine_path().display()
+                );
+            } else {
+                println!(
+                    "{} malformed line(s); run `task doctor --quarantine` to set them aside",
+                    report.bad.len()
+                );
                 std::process::exit(1);
             }
-            let latest = entries.last().unwrap();
-            println!("{} | {} | {}", latest.id, latest.project, latest.title);
-            if !latest.description.is_empty() {
-                for line in latest.description.lines() {
-                    println!("  {line}");
+        }
+        Commands::Config { action } => match action {
+            ConfigAction::List => {
+                output::print_settings(format, &config.settings(store.path(), &project));
+            }
+            ConfigAction::Get { key } => {
+                let settings = config.settings(store.path(), &project);
+                let Some(setting) = settings.iter().find(|s| s.key == key) else {
+                    fail(&format!(
+                        "unknown config key '{key}' (expected {})",
+                        config::KEYS.join(", ")
+                    ));
+                };
+                output::print_setting(format, setting);
+            }
+            ConfigAction::Set { key, value, global } if key == "id_format" => {
+                if global {
+                    fail("id_format is kept next to the log, not in a config file; drop --global");
+                }
+                let id_format: IdFormat = value.parse().unwrap_or_else(|e: String| fail(&e));
+                store.set_id_format(id_format).unwrap_or_else(|e| fail(&e));
+                if format == Format::Table {
+                    println!("{key} set in {}", store.sidecar("id_format").display());
                 }
-                println!();
             }
-            for entry in &entries {
-                if entry.note.is_empty() {
-                    println!("  {:<28} {}", entry.ts, entry.status);
+            ConfigAction::Set { key, value, global } => {
+                let path = if global {
+                    config::global_path()
+                        .unwrap_or_else(|| fail("cannot locate the global config: HOME not set"))
                 } else {
-                    let note_display: String = entry
-                        .note
-                        .lines()
-                        .enumerate()
-                        .map(|(i, l)| {
-                            if i == 0 {
-                                l.to_string()
-                            } else {
-                                format!("\n{:>42}{l}", "")
-                            }
-                        })
-                        .collect::<Vec<_>>()
-                        .join("");
-                    println!("  {:<28} {:<10} {}", entry.ts, entry.status, note_display);
+                    let cwd = std::env::current_dir().unwrap_or_default();
+                    config::find_project_file(&cwd).unwrap_or_else(|| {
+                        project::project_root()
+                            .unwrap_or(cwd)
+                            .join(config::PROJECT_FILE)
+                    })
+                };
+                config::set(&path, &key, &value).unwrap_or_else(|e| fail(&e));
+                if format == Format::Table {
+                    println!("{key} set in {}", path.display());
                 }
             }
-        }
+        },
+        Commands::Project { action } => match action {
+            ProjectAction::Show => {
+                output::print_project(format, &project, &store.project_aliases());
+            }
+            ProjectAction::Alias { old, to } => {
+                let to = to.unwrap_or_else(|| project.clone());
+                store
+                    .add_project_aliases(&[(old.clone(), to.clone())])
+                    .unwrap_or_else(|e| fail(&e));
+                if format == Format::Table {
+                    println!("{old} -> {to}");
+                }
+            }
+            ProjectAction::Migrate { dry_run } => {
+                let root = project::project_root();
+                let aliases = store.project_aliases();
+                let mut mapped = Vec::new();
+                for old in store.recorded_projects() {
+                    if !old.starts_with('/') || aliases.contains_key(&old) {
+                        continue;
+                    }
+                    let dir = std::path::Path::new(&old);
+                    let canonical = if root.as_deref() == Some(dir) {
+                        Some(project.clone())
+                    } else if dir.is_dir() {
+                        project::project_at(dir)
+                    } else {
+                        eprintln!("skipped {old}: directory not found; use `task project alias`");
+                        continue;
+                    };
+                    if let Some(canonical) = canonical.filter(|c| *c != old) {
+                        mapped.push((old, canonical));
+                    }
+                }
+                if !dry_run && !mapped.is_empty() {
+                    store
+                        .add_project_aliases(&mapped)
+                        .unwrap_or_else(|e| fail(&e));
+                }
+                if format == Format::Table {
+                    let verb = if dry_run { "would alias" } else { "aliased" };
+                    for (old, canonical) in &mapped {
+                        println!("{verb} {old} -> {canonical}");
+                    }
+                    if mapped.is_empty() {
+                        println!("nothing to migrate");
+                    }
+                }
+            }
+        },
         Commands::Init { global } => {
             let result = init::run_init(global);
             if !result.injected.is_empty() {
@@ -0,0 +1,633 @@
+use crate::compact::CompactReport;
+use crate::config::Setting;
+use crate::graph::{GraphNode, TaskGraph};
+use crate::project;
+use crate::search::SearchResult;
+use crate::stale::StaleTask;
+use crate::store::{self, EntryKind, TaskEntry};
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::IsTerminal as _;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Human-readable table / history
+    Table,
+    /// Pretty-printed JSON document
+    Json,
+    /// One compact JSON object per line
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// When stdout is a terminal and NO_COLOR is unset
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// JSON shape of `task get`: the current state plus every log entry for the ID.
+#[derive(Serialize)]
+pub struct TaskDetail<'a> {
+    pub task: &'a TaskEntry,
+    pub history: &'a [TaskEntry],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub subtasks: &'a [GraphNode<'a>],
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).expect("Failed to serialize output")
+    );
+}
+
+/// Print the entry appended by `create` / `update`. Table mode prints nothing:
+/// the caller emits the `TASK_*` marker lines instead.
+pub fn print_entry(format: Format, entry: &TaskEntry) {
+    match format {
+        Format::Table => {}
+        Format::Json => print_json(entry),
+        Format::Jsonl => println!("{}", entry.to_jsonl()),
+    }
+}
+
+/// With `tree`, sub-tasks are indented under their parent in table mode.
+/// `columns` overrides the default table columns.
+pub fn print_list(
+    format: Format,
+    tasks: &[TaskEntry],
+    graph: &TaskGraph,
+    tree: bool,
+    columns: Option<&[Column]>,
+) {
+    match format {
+        Format::Table => print_table(tasks, graph, tree, columns),
+        Format::Json => print_json(tasks),
+        Format::Jsonl => {
+            for task in tasks {
+                println!("{}", task.to_jsonl());
+            }
+        }
+    }
+}
+
+pub fn print_detail(format: Format, entries: &[TaskEntry], subtasks: &[GraphNode]) {
+    let Some(latest) = entries.last() else {
+        return;
+    };
+    match format {
+        Format::Table => {
+            print_history(latest, entries);
+            if !subtasks.is_empty() {
+                println!();
+                println!("  subtasks:");
+                print_graph_nodes(subtasks, 2);
+            }
+        }
+        Format::Json => print_json(&TaskDetail {
+            task: latest,
+            history: entries,
+            subtasks,
+        }),
+        Format::Jsonl => {
+            for entry in entries {
+                println!("{}", entry.to_jsonl());
+            }
+        }
+    }
+}
+
+pub fn print_stale(format: Format, stale: &[StaleTask]) {
+    match format {
+        Format::Table => {
+            if stale.is_empty() {
+                return;
+            }
+            println!("{:<10} {:<8} {:<26} TITLE", "ID", "IDLE", "LAST ACTIVITY");
+            for s in stale {
+                println!(
+                    "{:<10} {:<8} {:<26} {}",
+                    s.task.id, s.idle, s.last_activity, s.task.title
+                );
+            }
+        }
+        Format::Json => print_json(stale),
+        Format::Jsonl => {
+            for s in stale {
+                println!(
+                    "{}",
+                    serde_json::to_string(s).expect("Failed to serialize output")
+                );
+            }
+        }
+    }
+}
+
+pub fn print_settings(format: Format, settings: &[Setting]) {
+    match format {
+        Format::Table => {
+            for s in settings {
+                println!("{} = {}  # {}", s.key, s.value, s.source);
+            }
+        }
+        Format::Json => print_json(settings),
+        Format::Jsonl => {
+            for s in settings {
+                println!(
+                    "{}",
+                    serde_json::to_string(s).expect("Failed to serialize output")
+                );
+            }
+        }
+    }
+}
+
+/// Table mode prints just the value, with strings unquoted for scripts.
+pub fn print_setting(format: Format, setting: &Setting) {
+    match format {
+        Format::Table => match &setting.value {
+            toml::Value::String(s) => println!("{s}"),
+            value => println!("{value}"),
+        },
+        Format::Json => print_json(setting),
+        Format::Jsonl => println!(
+            "{}",
+            serde_json::to_string(setting).expect("Failed to serialize output")
+        ),
+    }
+}
+
+/// Wrap each span of `line` in bold red.
+fn highlight(line: &str, spans: &[std::ops::Range<usize>]) -> String {
+    let mut out = String::with_capacity(line.len() + spans.len() * 11);
+    let mut last = 0;
+    for span in spans {
+        out.push_str(&line[last..span.start]);
+        out.push_str("\x1b[1;31m");
+        out.push_str(&line[span.clone()]);
+        out.push_str("\x1b[0m");
+        last = span.end;
+    }
+    out.push_str(&line[last..]);
+    out
+}
+
+pub fn print_search(format: Format, results: &[SearchResult], color: bool) {
+    match format {
+        Format::Table => {
+            for result in results {
+                let task = &result.task;
+                println!(
+                    "{:<10} {:<8} {:<24} {}",
+                    task.id,
+                    task.status,
+                    project::short_project(&task.project),
+                    task.title
+                );
+                for m in &result.matches {
+                    let line = if color {
+                        highlight(&m.line, &m.spans)
+                    } else {
+                        m.line.clone()
+                    };
+                    match m.field {
+                        "note" => println!("  note ({} {}): {line}", m.ts, m.status),
+                        "comment" => println!("  comment ({}): {line}", m.ts),
+                        field => println!("  {field}: {line}"),
+                    }
+                }
+            }
+        }
+        Format::Json => print_json(results),
+        Format::Jsonl => {
+            for result in results {
+                println!(
+                    "{}",
+                    serde_json::to_string(result).expect("Failed to serialize output")
+                );
+            }
+        }
+    }
+}
+
+/// JSON shape of `task project show`.
+#[derive(Serialize)]
+pub struct ProjectView<'a> {
+    pub project: &'a str,
+    pub aliases: &'a BTreeMap<String, String>,
+}
+
+pub fn print_project(format: Format, project: &str, aliases: &BTreeMap<String, String>) {
+    match format {
+        Format::Table => {
+            println!("{project}");
+            if !aliases.is_empty() {
+                println!("aliases:");
+                for (old, canonical) in aliases {
+                    println!("  {old} -> {canonical}");
+                }
+            }
+        }
+        Format::Json => print_json(&ProjectView { project, aliases }),
+        Format::Jsonl => println!(
+            "{}",
+            serde_json::to_string(&ProjectView { project, aliases })
+                .expect("Failed to serialize output")
+        ),
+    }
+}
+
+/// JSON shape of `task heartbeat`.
+#[derive(Serialize)]
+pub struct HeartbeatView<'a> {
+    pub id: &'a str,
+    pub ts: &'a str,
+}
+
+pub fn print_heartbeat(format: Format, id: &str, ts: &str) {
+    match format {
+        Format::Table => println!("heartbeat: {id} {ts}"),
+        Format::Json => print_json(&HeartbeatView { id, ts }),
+        Format::Jsonl => println!(
+            "{}",
+            serde_json::to_string(&HeartbeatView { id, ts }).expect("Failed to serialize output")
+        ),
+    }
+}
+
+/// JSON shape of `task graph`.
+#[derive(Serialize)]
+pub struct TaskGraphView<'a> {
+    pub task: &'a TaskEntry,
+    pub upstream: &'a [GraphNode<'a>],
+    pub downstream: &'a [GraphNode<'a>],
+}
+
+pub fn print_compact(format: Format, report: &CompactReport, dry_run: bool) {
+    match format {
+        Format::Table => {
+            if report.tasks.is_empty() {
+                println!("nothing to compact");
+                return;
+            }
+            println!(
+                "{} {} task(s), {} entries into {}",
+                if dry_run {
+                    "would compact"
+                } else {
+                    "compacted"
+                },
+                report.tasks.len(),
+                report.entries,
+                report.segments.join(", ")
+            );
+        }
+        Format::Json => print_json(report),
+        Format::Jsonl => println!(
+            "{}",
+            serde_json::to_string(report).expect("Failed to serialize output")
+        ),
+    }
+}
+
+pub fn print_graph(
+    format: Format,
+    task: &TaskEntry,
+    upstream: &[GraphNode],
+    downstream: &[GraphNode],
+) {
+    let view = TaskGraphView {
+        task,
+        upstream,
+        downstream,
+    };
+    match format {
+        Format::Table => {
+            println!("{} {} {}", task.id, task.status, task.title);
+            println!("upstream (depends on):");
+            print_graph_nodes(upstream, 1);
+            println!("downstream (blocks):");
+            print_graph_nodes(downstream, 1);
+        }
+        Format::Json => print_json(&view),
+        Format::Jsonl => println!(
+            "{}",
+            serde_json::to_string(&view).expect("Failed to serialize output")
+        ),
+    }
+}
+
+fn print_graph_nodes(nodes: &[GraphNode], depth: usize) {
+    if nodes.is_empty() && depth == 1 {
+        println!("  (none)");
+    }
+    for node in nodes {
+        let rollup = node.rollup.map(|r| format!(" ({r})")).unwrap_or_default();
+        let repeated = if node.repeated { " (cycle)" } else { "" };
+        println!(
+            "{:indent$}{:<10} {:<8} {}{rollup}{repeated}",
+            "",
+            node.id,
+            node.status,
+            node.title,
+            indent = depth * 2
+        );
+        print_graph_nodes(&node.children, depth + 1);
+    }
+}
+
+/// A column of the `task list` table, selectable with the `columns` config key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Id,
+    Status,
+    Priority,
+    Holder,
+    Project,
+    Tags,
+    Worktree,
+    Branch,
+    Actor,
+    Title,
+}
+
+impl Column {
+    const ALL: &[Column] = &[
+        Column::Id,
+        Column::Status,
+        Column::Priority,
+        Column::Holder,
+        Column::Project,
+        Column::Tags,
+        Column::Worktree,
+        Column::Branch,
+        Column::Actor,
+        Column::Title,
+    ];
+
+    /// Shown when no columns are configured; PRI and HOLDER are then dropped
+    /// unless some listed task uses them.
+    pub const DEFAULT: &[Column] = &[
+        Column::Id,
+        Column::Status,
+        Column::Priority,
+        Column::Holder,
+        Column::Project,
+        Column::Title,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Id => "id",
+            Column::Status => "status",
+            Column::Priority => "priority",
+            Column::Holder => "holder",
+            Column::Project => "project",
+            Column::Tags => "tags",
+            Column::Worktree => "worktree",
+            Column::Branch => "branch",
+            Column::Actor => "actor",
+            Column::Title => "title",
+        }
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            Column::Id => "ID",
+            Column::Status => "STATUS",
+            Column::Priority => "PRI",
+            Column::Holder => "HOLDER",
+            Column::Project => "PROJECT",
+            Column::Tags => "TAGS",
+            Column::Worktree => "WORKTREE",
+            Column::Branch => "BRANCH",
+            Column::Actor => "ACTOR",
+            Column::Title => "TITLE",
+        }
+    }
+
+    /// Fixed-width columns pad to this width and let longer values overflow;
+    /// the others are as wide as their widest value.
+    fn fixed_width(self) -> Option<usize> {
+        match self {
+            Column::Id => Some(10),
+            Column::Status => Some(8),
+            Column::Priority => Some(4),
+            Column::Project => Some(24),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Column::ALL
+            .iter()
+            .copied()
+            .find(|c| c.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Column::ALL.iter().map(|c| c.name()).collect();
+                format!("unknown column '{s}' (expected {})", names.join(", "))
+            })
+    }
+}
+
+fn holder_cell(task: &TaskEntry, now: DateTime<Local>) -> String {
+    match (&task.claimed_by, task.live_claim(now)) {
+        (_, Some(holder)) => holder.to_string(),
+        (Some(holder), None) if task.lease_expired(now) => format!("{holder} (expired)"),
+        _ => String::new(),
+    }
+}
+
+fn print_table(tasks: &[TaskEntry], graph: &TaskGraph, tree: bool, columns: Option<&[Column]>) {
+    if tasks.is_empty() {
+        return;
+    }
+    let rows = if tree {
+        graph.nest(tasks)
+    } else {
+        tasks.iter().map(|t| (0, t)).collect()
+    };
+    let now = Local::now();
+    // Without configured columns, PRI and HOLDER only appear once some listed
+    // task uses them, so output for logs that never do is unchanged.
+    let columns: Vec<Column> = match columns {
+        Some(columns) => columns.to_vec(),
+        None => Column::DEFAULT
+            .iter()
+            .copied()
+            .filter(|c| match c {
+                Column::Priority => tasks.iter().any(|t| t.priority.is_some()),
+                Column::Holder => tasks.iter().any(|t| !holder_cell(t, now).is_empty()),
+                _ => true,
+            })
+            .collect(),
+    };
+    let cell = |column: Column, depth: usize, task: &TaskEntry| match column {
+        Column::Id => task.id.clone(),
+        Column::Status => task.status.clone(),
+        Column::Priority => task.priority.map_or("-".to_string(), |p| format!("p{p}")),
+        Column::Holder => holder_cell(task, now),
+        Column::Project => project::short_project(&task.project).to_string(),
+        Column::Tags => task.tags.join(","),
+        Column::Worktree => task
+            .worktree
+            .as_deref()
+            .map_or("-", project::short_project)
+            .to_string(),
+        Column::Branch => task.branch.as_deref().unwrap_or("-").to_string(),
+        Column::Actor => task.actor.as_deref().unwrap_or("-").to_string(),
+        Column::Title => {
+            let rollup = graph
+                .rollup(&task.id)
+                .map(|r| format!(" ({r})"))
+                .unwrap_or_default();
+            format!("{:indent$}{}{rollup}", "", task.title, indent = depth * 2)
+        }
+    };
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|&(depth, task)| columns.iter().map(|&c| cell(c, depth, task)).collect())
+        .collect();
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            c.fixed_width().unwrap_or_else(|| {
+                cells
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    .max(c.header().len())
+            })
+        })
+        .collect();
+    let print_row = |row: Vec<&str>| {
+        let last = row.len() - 1;
+        let line: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                if i == last {
+                    value.to_string()
+                } else {
+                    format!("{value:<w$}", w = widths[i])
+                }
+            })
+            .collect();
+        println!("{}", line.join(" "));
+    };
+    print_row(columns.iter().map(|c| c.header()).collect());
+    for row in &cells {
+        print_row(row.iter().map(String::as_str).collect());
+    }
+}
+
+fn print_history(latest: &TaskEntry, entries: &[TaskEntry]) {
+    println!("{} | {} | {}", latest.id, latest.project, latest.title);
+    if let Some(parent) = &latest.parent {
+        println!("  parent: {parent}");
+    }
+    if let Some(priority) = latest.priority {
+        println!("  priority: p{priority}");
+    }
+    if !latest.tags.is_empty() {
+        println!("  tags: {}", latest.tags.join(", "));
+    }
+    if !latest.depends_on.is_empty() {
+        println!("  depends on: {}", latest.depends_on.join(", "));
+    }
+    if let Some(worktree) = &latest.worktree {
+        match &latest.branch {
+            Some(branch) => println!("  worktree: {worktree} ({branch})"),
+            None => println!("  worktree: {worktree}"),
+        }
+    }
+    if !latest.description.is_empty() {
+        for line in latest.description.lines() {
+            println!("  {line}");
+        }
+        println!();
+    }
+    // Entries written before actors were recorded show a blank actor.
+    let actors: Vec<String> = entries.iter().map(actor_label).collect();
+    let actor_width = actors.iter().map(|a| a.chars().count()).max().unwrap_or(0);
+    for (i, (entry, actor)) in entries.iter().zip(&actors).enumerate() {
+        let actor = if actor_width > 0 {
+            format!("{actor:<actor_width$} ")
+        } else {
+            String::new()
+        };
+        let indent = 42 + actor.chars().count();
+        // Comments keep the status; mark them so they don't read as transitions.
+        let status = if entry.kind == Some(EntryKind::Comment) {
+            "(comment)"
+        } else {
+            entry.status.as_str()
+        };
+        if entry.note.is_empty() {
+            let line = format!("  {:<28} {status:<10} {actor}", entry.ts);
+            println!("{}", line.trim_end());
+        } else {
+            let note_display: String = entry
+                .note
+                .lines()
+                .enumerate()
+                .map(|(i, l)| {
+                    if i == 0 {
+                        l.to_string()
+                    } else {
+                        format!("\n{:>indent$}{l}", "")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("");
+            println!("  {:<28} {status:<10} {actor}{note_display}", entry.ts);
+        }
+        if entry.kind == Some(EntryKind::Undo)
+            && let Some(undone) = store::effective_history(&entries[..i]).last()
+        {
+            let what = if undone.deleted {
+                "delete"
+            } else {
+                undone.status.as_str()
+            };
+            println!("{:>indent$}undo of {what} ({})", "", undone.ts);
+        }
+        // Fields changed by this entry, e.g. by `task edit`.
+        if i > 0 {
+            for change in entry.changes_from(&entries[i - 1]) {
+                println!("{:>indent$}{change}", "");
+            }
+        }
+    }
+}
+
+/// `actor`, or `actor@session` with the session shortened to 8 characters.
+fn actor_label(entry: &TaskEntry) -> String {
+    let actor = entry.actor.as_deref().unwrap_or_default();
+    match &entry.session {
+        Some(session) => format!("{actor}@{}", session.chars().take(8).collect::<String>()),
+        None => actor.to_string(),
+    }
+}
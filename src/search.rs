@@ -0,0 +1,192 @@
+use crate::store::{EntryKind, TaskEntry};
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// A line of a title, description, note or comment that matched the query.
+#[derive(Debug, Serialize)]
+pub struct Match {
+    pub field: &'static str,
+    /// Entry the text was found in; for notes this is the transition.
+    pub ts: String,
+    pub status: String,
+    pub line: String,
+    /// Byte ranges of the matches within `line`.
+    #[serde(skip)]
+    pub spans: Vec<Range<usize>>,
+}
+
+/// A task with at least one match anywhere in its history.
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub task: TaskEntry,
+    pub matches: Vec<Match>,
+}
+
+/// Compile the query; without `regex` it is matched literally. Matching
+/// ignores case unless `case_sensitive`.
+pub fn matcher(query: &str, regex: bool, case_sensitive: bool) -> Result<Regex, String> {
+    let pattern = if regex {
+        query.to_string()
+    } else {
+        regex::escape(query)
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| format!("invalid regex '{query}': {e}"))
+}
+
+fn matching_lines(re: &Regex, text: &str) -> Vec<(String, Vec<Range<usize>>)> {
+    text.lines()
+        .filter_map(|line| {
+            let spans: Vec<Range<usize>> = re.find_iter(line).map(|m| m.range()).collect();
+            (!spans.is_empty()).then(|| (line.to_string(), spans))
+        })
+        .collect()
+}
+
+/// Search every entry of every task in `tasks` (latest states). Titles and
+/// descriptions repeat across a task's history, so each distinct text is
+/// reported once, from the entry where it first appeared; notes are reported
+/// per transition. Results keep the order of `tasks`.
+pub fn search(re: &Regex, entries: &[TaskEntry], tasks: &[TaskEntry]) -> Vec<SearchResult> {
+    let mut matches: HashMap<&str, Vec<Match>> = HashMap::new();
+    let mut seen: HashMap<(&str, &'static str), Vec<&str>> = HashMap::new();
+    for entry in entries {
+        let note = if entry.kind == Some(EntryKind::Comment) {
+            "comment"
+        } else {
+            "note"
+        };
+        let fields = [
+            ("title", entry.title.as_str()),
+            ("description", entry.description.as_str()),
+            (note, entry.note.as_str()),
+        ];
+        for (field, text) in fields {
+            if field == "title" || field == "description" {
+                let known = seen.entry((&entry.id, field)).or_default();
+                if known.contains(&text) {
+                    continue;
+                }
+                known.push(text);
+            }
+            for (line, spans) in matching_lines(re, text) {
+                matches.entry(&entry.id).or_default().push(Match {
+                    field,
+                    ts: entry.ts.clone(),
+                    status: entry.status.clone(),
+                    line,
+                    spans,
+                });
+            }
+        }
+    }
+    tasks
+        .iter()
+        .filter_map(|task| {
+            let found = matches.remove(task.id.as_str())?;
+            Some(SearchResult {
+                task: task.clone(),
+                matches: found,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, status: &str, title: &str, note: &str) -> TaskEntry {
+        TaskEntry {
+            id: id.into(),
+            status: status.into(),
+            title: title.into(),
+            note: note.into(),
+            ..TaskEntry::default()
+        }
+    }
+
+    #[test]
+    fn literal_match_ignores_case_by_default() {
+        let re = matcher("oauth.callback", false, false).unwrap();
+        assert!(re.is_match("Fix OAUTH.callback"));
+        assert!(!re.is_match("Fix OAuth callback"));
+        let re = matcher("OAuth", false, true).unwrap();
+        assert!(!re.is_match("oauth"));
+    }
+
+    #[test]
+    fn regex_mode_and_errors() {
+        assert!(matcher(r"o\w+h", true, false).unwrap().is_match("OAuth"));
+        assert!(
+            matcher("(", true, false)
+                .unwrap_err()
+                .contains("invalid regex")
+        );
+    }
+
+    #[test]
+    fn reports_title_once_and_each_matching_note() {
+        let entries = vec![
+            entry("a", "todo", "OAuth callback", ""),
+            entry(
+                "a",
+                "blocked",
+                "OAuth callback",
+                "waiting on oauth app\nother line",
+            ),
+            entry("b", "todo", "Unrelated", ""),
+            entry("a", "doing", "OAuth callback", "oauth app approved"),
+        ];
+        let tasks = vec![
+            entry("a", "doing", "OAuth callback", ""),
+            entry("b", "todo", "Unrelated", ""),
+        ];
+        let results = search(&matcher("oauth", false, false).unwrap(), &entries, &tasks);
+        assert_eq!(results.len(), 1);
+        let found: Vec<(&str, &str, &str)> = results[0]
+            .matches
+            .iter()
+            .map(|m| (m.field, m.status.as_str(), m.line.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("title", "todo", "OAuth callback"),
+                ("note", "blocked", "waiting on oauth app"),
+                ("note", "doing", "oauth app approved"),
+            ]
+        );
+        assert_eq!(results[0].matches[1].spans, vec![11..16]);
+    }
+
+    #[test]
+    fn comments_are_labelled() {
+        let created = entry("a", "blocked", "Login", "");
+        let entries = vec![
+            created.clone(),
+            created.comment("use the staging oauth app".into()),
+        ];
+        let results = search(
+            &matcher("oauth", false, false).unwrap(),
+            &entries,
+            &[created],
+        );
+        assert_eq!(results[0].matches[0].field, "comment");
+    }
+
+    #[test]
+    fn renamed_titles_are_reported_again() {
+        let entries = vec![
+            entry("a", "todo", "Login flow", ""),
+            entry("a", "todo", "Login flow v2", ""),
+        ];
+        let tasks = vec![entry("a", "todo", "Login flow v2", "")];
+        let results = search(&matcher("login", false, false).unwrap(), &entries, &tasks);
+        assert_eq!(results[0].matches.len(), 2);
+    }
+}
@@ -0,0 +1,288 @@
+use crate::store::{BadLine, LogScan, TaskEntry, resolve_in};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read as _, Seek as _, SeekFrom};
+use std::path::Path;
+
+/// Bump whenever the way entries are folded into `tasks` changes, so stale
+/// snapshots written by an older binary are rebuilt instead of trusted.
+const SNAPSHOT_VERSION: u32 = 3;
+
+/// Bytes before `offset` that are hashed to detect a rewritten log.
+const TAIL_WINDOW: u64 = 4096;
+
+/// Derived cache of `tasks.log` (kept in `tasks.log.snapshot`): the latest
+/// state of every task plus the byte offset it was computed up to.
+///
+/// The log stays the single source of truth. On every read the snapshot is
+/// brought up to date by folding only the bytes appended since `offset`, and
+/// it is rebuilt from scratch if the log got shorter or the bytes just before
+/// `offset` no longer hash to `tail_hash`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    version: u32,
+    offset: u64,
+    tail_hash: u64,
+    /// Number of lines folded so far, used to number bad lines.
+    lines: usize,
+    /// Malformed lines seen so far.
+    pub bad: Vec<BadLine>,
+    /// Entries of an unknown kind skipped so far.
+    pub unknown: usize,
+    /// Latest entry per ID, in first-seen order.
+    pub tasks: Vec<TaskEntry>,
+    /// `ts` of the first entry per ID, i.e. when the task was created.
+    pub created: HashMap<String, String>,
+    #[serde(skip)]
+    index: HashMap<String, usize>,
+}
+
+impl Snapshot {
+    fn empty() -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            ..Self::default()
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&TaskEntry> {
+        self.index.get(id).map(|&i| &self.tasks[i])
+    }
+
+    /// Latest state of the task a full ID or unambiguous prefix names.
+    /// Deleted tasks are refused, as in `TaskStore::resolve_id`.
+    pub fn resolve(&self, prefix: &str) -> Result<&TaskEntry, String> {
+        let id = resolve_in(&self.tasks, prefix)?;
+        let task = self.get(&id).expect("resolved ID is in the snapshot");
+        if task.deleted {
+            return Err(format!(
+                "task '{id}' was deleted (`task undo {id}` restores it)"
+            ));
+        }
+        Ok(task)
+    }
+
+    /// Latest state of every task that is not deleted.
+    pub fn live_tasks(&self) -> Vec<TaskEntry> {
+        self.tasks.iter().filter(|t| !t.deleted).cloned().collect()
+    }
+
+    fn apply(&mut self, entry: TaskEntry) {
+        self.created
+            .entry(entry.id.clone())
+            .or_insert_with(|| entry.ts.clone());
+        match self.index.get(&entry.id) {
+            Some(&i) => self.tasks[i] = entry,
+            None => {
+                self.index.insert(entry.id.clone(), self.tasks.len());
+                self.tasks.push(entry);
+            }
+        }
+    }
+
+    fn reindex(&mut self) {
+        self.index = self
+            .tasks
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.id.clone(), i))
+            .collect();
+    }
+}
+
+/// FNV-1a: tiny, and stable across Rust versions unlike `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn tail_hash(file: &mut File, offset: u64) -> Option<u64> {
+    let start = offset.saturating_sub(TAIL_WINDOW);
+    let mut buf = vec![0u8; (offset - start) as usize];
+    file.seek(SeekFrom::Start(start)).ok()?;
+    file.read_exact(&mut buf).ok()?;
+    Some(fnv1a(&buf))
+}
+
+fn read_cached(path: &Path) -> Option<Snapshot> {
+    let bytes = fs::read(path).ok()?;
+    let mut snapshot: Snapshot = serde_json::from_slice(&bytes).ok()?;
+    if snapshot.version != SNAPSHOT_VERSION {
+        return None;
+    }
+    snapshot.reindex();
+    Some(snapshot)
+}
+
+/// Best effort: a failed write only means the next read does more work.
+fn write_cached(path: &Path, snapshot: &Snapshot) {
+    let Ok(json) = serde_json::to_vec(snapshot) else {
+        return;
+    };
+    let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+    if fs::write(&tmp, json).is_ok() && fs::rename(&tmp, path).is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+}
+
+/// Load the snapshot for `log`, catching up with any appended lines.
+pub fn load(log: &Path, cache: &Path) -> Snapshot {
+    let Ok(mut file) = File::open(log) else {
+        return Snapshot::empty();
+    };
+    let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+    let mut snapshot = read_cached(cache)
+        .filter(|s| s.offset <= len && tail_hash(&mut file, s.offset) == Some(s.tail_hash))
+        .unwrap_or_else(Snapshot::empty);
+    if snapshot.offset == len {
+        return snapshot;
+    }
+
+    let mut appended = Vec::new();
+    if file.seek(SeekFrom::Start(snapshot.offset)).is_err()
+        || file.read_to_end(&mut appended).is_err()
+    {
+        return snapshot;
+    }
+    // Only fold complete lines; a partial last line may still be being written.
+    let Some(end) = appended.iter().rposition(|&b| b == b'\n').map(|i| i + 1) else {
+        return snapshot;
+    };
+    let content = String::from_utf8_lossy(&appended[..end]);
+    let scan = LogScan::parse_from(&content, snapshot.lines);
+    snapshot.lines += content.lines().count();
+    snapshot.bad.extend(scan.bad);
+    snapshot.unknown += scan.unknown;
+    for entry in scan.entries {
+        snapshot.apply(entry);
+    }
+    snapshot.offset += end as u64;
+    if let Some(hash) = tail_hash(&mut file, snapshot.offset) {
+        snapshot.tail_hash = hash;
+        write_cached(cache, &snapshot);
+    }
+    snapshot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(id: &str, status: &str) -> String {
+        format!(
+            "{{\"ts\":\"2026-02-22T14:30:00+09:00\",\"id\":\"{id}\",\"project\":\"p\",\"status\":\"{status}\",\"title\":\"T\",\"description\":\"\",\"note\":\"\"}}\n"
+        )
+    }
+
+    fn paths() -> (tempfile::TempDir, std::path::PathBuf, std::path::PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("tasks.log");
+        let cache = dir.path().join("tasks.log.snapshot");
+        (dir, log, cache)
+    }
+
+    fn statuses(snapshot: &Snapshot) -> Vec<(&str, &str)> {
+        snapshot
+            .tasks
+            .iter()
+            .map(|t| (t.id.as_str(), t.status.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn missing_log_is_empty() {
+        let (_dir, log, cache) = paths();
+        assert!(load(&log, &cache).tasks.is_empty());
+        assert!(!cache.exists());
+    }
+
+    #[test]
+    fn folds_latest_state_in_first_seen_order() {
+        let (_dir, log, cache) = paths();
+        fs::write(
+            &log,
+            line("b", "todo") + &line("a", "todo") + &line("b", "done"),
+        )
+        .unwrap();
+        let snapshot = load(&log, &cache);
+        assert_eq!(statuses(&snapshot), vec![("b", "done"), ("a", "todo")]);
+        assert_eq!(snapshot.get("b").unwrap().status, "done");
+        assert_eq!(snapshot.created["b"], "2026-02-22T14:30:00+09:00");
+        assert!(cache.exists());
+    }
+
+    #[test]
+    fn catches_up_incrementally() {
+        let (_dir, log, cache) = paths();
+        fs::write(&log, line("a", "todo")).unwrap();
+        let first = load(&log, &cache);
+        assert_eq!(first.offset, fs::metadata(&log).unwrap().len());
+
+        let mut content = fs::read_to_string(&log).unwrap();
+        content.push_str(&line("a", "doing"));
+        content.push_str(&line("c", "todo"));
+        fs::write(&log, content).unwrap();
+        let second = load(&log, &cache);
+        assert_eq!(statuses(&second), vec![("a", "doing"), ("c", "todo")]);
+        assert_eq!(second.offset, fs::metadata(&log).unwrap().len());
+    }
+
+    #[test]
+    fn ignores_partial_last_line() {
+        let (_dir, log, cache) = paths();
+        let partial = line("b", "todo");
+        fs::write(&log, line("a", "todo") + &partial[..20]).unwrap();
+        let snapshot = load(&log, &cache);
+        assert_eq!(statuses(&snapshot), vec![("a", "todo")]);
+        assert!(snapshot.bad.is_empty());
+
+        fs::write(&log, line("a", "todo") + &partial).unwrap();
+        assert_eq!(
+            statuses(&load(&log, &cache)),
+            vec![("a", "todo"), ("b", "todo")]
+        );
+    }
+
+    #[test]
+    fn rebuilds_when_log_shrinks() {
+        let (_dir, log, cache) = paths();
+        fs::write(&log, line("a", "todo") + &line("b", "todo")).unwrap();
+        load(&log, &cache);
+        fs::write(&log, line("c", "todo")).unwrap();
+        assert_eq!(statuses(&load(&log, &cache)), vec![("c", "todo")]);
+    }
+
+    #[test]
+    fn rebuilds_when_content_changes_at_same_length() {
+        let (_dir, log, cache) = paths();
+        fs::write(&log, line("a", "todo")).unwrap();
+        load(&log, &cache);
+        fs::write(&log, line("z", "todo")).unwrap();
+        assert_eq!(statuses(&load(&log, &cache)), vec![("z", "todo")]);
+    }
+
+    #[test]
+    fn rebuilds_when_cache_is_corrupt() {
+        let (_dir, log, cache) = paths();
+        fs::write(&log, line("a", "todo")).unwrap();
+        fs::write(&cache, "garbage").unwrap();
+        assert_eq!(statuses(&load(&log, &cache)), vec![("a", "todo")]);
+    }
+
+    #[test]
+    fn numbers_bad_lines_across_increments() {
+        let (_dir, log, cache) = paths();
+        fs::write(&log, line("a", "todo") + "oops\n").unwrap();
+        load(&log, &cache);
+        let mut content = fs::read_to_string(&log).unwrap();
+        content.push_str(&line("a", "doing"));
+        content.push_str("again\n");
+        fs::write(&log, content).unwrap();
+        let snapshot = load(&log, &cache);
+        let lines: Vec<usize> = snapshot.bad.iter().map(|b| b.line).collect();
+        assert_eq!(lines, vec![2, 4]);
+    }
+}
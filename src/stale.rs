@@ -0,0 +1,88 @@
+use crate::store::{Origin, TaskEntry, TaskStore};
+use crate::time::{format_duration, parse_ts};
+use crate::workflow::Workflow;
+use chrono::{DateTime, Duration, Local};
+use serde::Serialize;
+
+/// A task in the `doing` status with no log entry or heartbeat for a while.
+#[derive(Serialize)]
+pub struct StaleTask {
+    pub task: TaskEntry,
+    /// Latest of the task's last log entry and its last heartbeat.
+    pub last_activity: String,
+    pub idle: String,
+}
+
+/// Tasks in the `doing` status (in `project`, or everywhere when `None`)
+/// whose last activity is older than `older_than`, most idle first.
+pub fn find_stale(
+    store: &TaskStore,
+    project: Option<&str>,
+    doing: &str,
+    older_than: Duration,
+    now: DateTime<Local>,
+) -> Vec<StaleTask> {
+    let beats = store.heartbeats();
+    let mut stale: Vec<(Duration, StaleTask)> = store
+        .current_tasks(project, Some(doing))
+        .into_iter()
+        .filter(|task| !task.archived)
+        .filter_map(|task| {
+            let last_activity = [Some(&task.ts), beats.get(&task.id)]
+                .into_iter()
+                .flatten()
+                .filter_map(|ts| parse_ts(ts).map(|t| (t, ts.clone())))
+                .max_by_key(|(t, _)| *t)?;
+            let idle = now.signed_duration_since(last_activity.0);
+            (idle > older_than).then(|| {
+                (
+                    idle,
+                    StaleTask {
+                        task,
+                        last_activity: last_activity.1,
+                        idle: format_duration(idle),
+                    },
+                )
+            })
+        })
+        .collect();
+    stale.sort_by_key(|(idle, _)| std::cmp::Reverse(*idle));
+    stale.into_iter().map(|(_, s)| s).collect()
+}
+
+/// Move stale tasks to `blocked` (or what the workflow aliases it to) with a
+/// generated note, skipping any that saw activity since they were found.
+/// Returns the appended entries.
+pub fn block_stale(
+    store: &TaskStore,
+    project: Option<&str>,
+    workflow: &Workflow,
+    older_than: Duration,
+    origin: &Origin,
+) -> Result<Vec<TaskEntry>, String> {
+    let doing = workflow.canonical("doing");
+    let blocked_status = workflow.resolve_builtin("blocked", "task stale --block")?;
+    if workflow.check_transition(&doing, &blocked_status).is_err() {
+        return Err(format!(
+            "the workflow does not allow '{doing}' -> '{blocked_status}', \
+             so stale tasks cannot be blocked"
+        ));
+    }
+    let mut lock = store.lock()?;
+    let stale = find_stale(store, project, &doing, older_than, Local::now());
+    let mut blocked = Vec::new();
+    for StaleTask {
+        task,
+        last_activity,
+        idle,
+    } in stale
+    {
+        let note = format!("stale: no activity for {idle} (since {last_activity})");
+        let mut entry = task.successor(blocked_status.clone(), note);
+        entry.release();
+        entry.stamp(origin);
+        lock.append(&entry);
+        blocked.push(entry);
+    }
+    Ok(blocked)
+}
@@ -1,14 +1,55 @@
-use chrono::Local;
+use crate::snapshot::{self, Snapshot};
+use crate::time::{now_ts, parse_ts};
+use chrono::{DateTime, Local};
 use rand::RngExt;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::env;
-use std::fs;
-use std::io::Write as _;
-use std::path::PathBuf;
+use std::fs::{self, File, TryLockError};
+use std::io::{Read as _, Seek as _, SeekFrom, Write as _};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Version written into the `v` field of new entries. Lines without one are
+/// version 1, from before entries carried a `kind`.
+pub const SCHEMA_VERSION: u32 = 2;
+
+const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// What an entry records. Every entry still carries the task's full state;
+/// the kind says which command produced it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    Create,
+    /// A status change, including claims and `stale --block`.
+    Transition,
+    Edit,
+    Archive,
+    Delete,
+    /// A copy of the state before the entry it undoes.
+    Undo,
+    /// A remark in `note`; the state is unchanged and undo passes over it.
+    Comment,
+    /// Written by a newer version; such entries are skipped when reading.
+    #[serde(other)]
+    Unknown,
+}
+
+fn legacy_version() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct TaskEntry {
+    #[serde(default = "legacy_version")]
+    pub v: u32,
+    /// `None` on version 1 lines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<EntryKind>,
     pub ts: String,
     pub id: String,
     pub project: String,
@@ -16,6 +57,60 @@ pub struct TaskEntry {
     pub title: String,
     pub description: String,
     pub note: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// 0 is the most important. Tasks without a priority sort after all others.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u8>,
+    /// IDs of tasks that must be `done` before this one is ready.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// ID of the parent task (epic) this is a sub-task of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Agent holding the lease taken by `task claim`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claimed_by: Option<String>,
+    /// When the lease lapses and the task returns to the pool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_until: Option<String>,
+    /// Worktree (git toplevel) the entry was written from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worktree: Option<String>,
+    /// Branch checked out in that worktree.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Who wrote the entry: an agent name such as `claude`, or `human`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    /// Session of the writing agent, from `TASK_SESSION`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    /// Set by `task archive`: hidden from default views, kept in the log.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+    /// Tombstone written by `task delete`: the task is hidden everywhere
+    /// except `task get`, until `task undo` restores it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deleted: bool,
+}
+
+/// Where an entry is written from, stamped onto every appended entry.
+#[derive(Debug, Clone, Default)]
+pub struct Origin {
+    pub worktree: Option<String>,
+    pub branch: Option<String>,
+    pub actor: Option<String>,
+    pub session: Option<String>,
+}
+
+/// Parse `p0`..`p9` or a bare `0`..`9`.
+pub fn parse_priority(s: &str) -> Result<u8, String> {
+    let digits = s.strip_prefix(['p', 'P']).unwrap_or(s);
+    match digits.parse::<u8>() {
+        Ok(n) if n <= 9 => Ok(n),
+        _ => Err(format!("invalid priority '{s}' (expected p0-p9 or 0-9)")),
+    }
 }
 
 impl TaskEntry {
@@ -28,46 +123,397 @@ impl TaskEntry {
         note: String,
     ) -> Self {
         Self {
-            ts: Local::now().format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+            v: SCHEMA_VERSION,
+            kind: Some(EntryKind::Create),
+            ts: now_ts(),
             id,
             project,
             status,
             title,
             description,
             note,
+            ..Self::default()
         }
     }
 
+    /// Next entry in this task's history: a transition with a fresh timestamp,
+    /// the given status and note, and every other field carried forward except
+    /// the `archived` and `deleted` markers, so touching an archived task
+    /// brings it back.
+    pub fn successor(&self, status: String, note: String) -> Self {
+        Self {
+            v: SCHEMA_VERSION,
+            kind: Some(EntryKind::Transition),
+            ts: now_ts(),
+            status,
+            note,
+            archived: false,
+            deleted: false,
+            ..self.clone()
+        }
+    }
+
+    /// A comment on this task: the same state, including the `archived`
+    /// marker, with `text` as the note.
+    pub fn comment(&self, text: String) -> Self {
+        Self {
+            v: SCHEMA_VERSION,
+            kind: Some(EntryKind::Comment),
+            ts: now_ts(),
+            note: text,
+            ..self.clone()
+        }
+    }
+
+    /// An edit of this task: the same state, including the `archived` marker
+    /// and any lease, for the caller to change fields on.
+    pub fn edited(&self) -> Self {
+        Self {
+            v: SCHEMA_VERSION,
+            kind: Some(EntryKind::Edit),
+            ts: now_ts(),
+            note: String::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Add tags, skipping blanks and ones already present.
+    pub fn add_tags(&mut self, tags: impl IntoIterator<Item = String>) {
+        for tag in tags {
+            let tag = tag.trim();
+            if !tag.is_empty() && !self.tags.iter().any(|t| t == tag) {
+                self.tags.push(tag.to_string());
+            }
+        }
+    }
+
+    pub fn remove_tags(&mut self, tags: &[String]) {
+        self.tags.retain(|t| !tags.iter().any(|r| r.trim() == t));
+    }
+
+    /// Fields other than status and note that differ from `prev`, the entry
+    /// before this one, e.g. `title: Fxi login -> Fix login`.
+    pub fn changes_from(&self, prev: &TaskEntry) -> Vec<String> {
+        fn added_removed(label: &str, old: &[String], new: &[String]) -> Option<String> {
+            let added = new
+                .iter()
+                .filter(|v| !old.contains(v))
+                .map(|v| format!("+{v}"));
+            let removed = old
+                .iter()
+                .filter(|v| !new.contains(v))
+                .map(|v| format!("-{v}"));
+            let diff: Vec<String> = added.chain(removed).collect();
+            (!diff.is_empty()).then(|| format!("{label}: {}", diff.join(" ")))
+        }
+        let or_none = |v: Option<String>| v.unwrap_or_else(|| "none".into());
+
+        let mut changes = Vec::new();
+        if self.title != prev.title {
+            changes.push(format!("title: {} -> {}", prev.title, self.title));
+        }
+        if self.project != prev.project {
+            changes.push(format!("project: {} -> {}", prev.project, self.project));
+        }
+        if self.description != prev.description {
+            changes.push("description: updated".into());
+        }
+        if self.priority != prev.priority {
+            changes.push(format!(
+                "priority: {} -> {}",
+                or_none(prev.priority.map(|p| format!("p{p}"))),
+                or_none(self.priority.map(|p| format!("p{p}")))
+            ));
+        }
+        if self.parent != prev.parent {
+            changes.push(format!(
+                "parent: {} -> {}",
+                or_none(prev.parent.clone()),
+                or_none(self.parent.clone())
+            ));
+        }
+        changes.extend(added_removed("tags", &prev.tags, &self.tags));
+        changes.extend(added_removed(
+            "depends on",
+            &prev.depends_on,
+            &self.depends_on,
+        ));
+        changes
+    }
+
+    /// The agent holding an unexpired lease on this task, if any. Leases are
+    /// released whenever a task leaves the status `task claim` moved it to,
+    /// so a lease alone marks a claimed task whatever the workflow calls it.
+    pub fn live_claim(&self, now: DateTime<Local>) -> Option<&str> {
+        let until = parse_ts(self.lease_until.as_deref()?)?;
+        self.claimed_by.as_deref().filter(|_| until > now)
+    }
+
+    /// A claimed task whose lease ran out, e.g. because its agent crashed.
+    pub fn lease_expired(&self, now: DateTime<Local>) -> bool {
+        self.claimed_by.is_some() && self.live_claim(now).is_none()
+    }
+
+    pub fn stamp(&mut self, origin: &Origin) {
+        self.worktree = origin.worktree.clone();
+        self.branch = origin.branch.clone();
+        self.actor = origin.actor.clone();
+        self.session = origin.session.clone();
+    }
+
+    pub fn release(&mut self) {
+        self.claimed_by = None;
+        self.lease_until = None;
+    }
+
+    /// Sort key for priority-aware listings.
+    pub fn priority_rank(&self) -> u8 {
+        self.priority.unwrap_or(u8::MAX)
+    }
+
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| self.tags.iter().any(|t| t == tag))
+    }
+
     pub fn to_jsonl(&self) -> String {
         serde_json::to_string(self).expect("Failed to serialize TaskEntry")
     }
 
+    #[cfg(test)]
     pub fn from_jsonl(line: &str) -> Option<Self> {
-        serde_json::from_str(line).ok()
+        Self::parse(line).ok()
+    }
+
+    pub fn parse(line: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(line)
+    }
+
+    /// Whether a line is an entry of a kind this version does not know. Such
+    /// entries may lack fields every known kind has, so only `kind` is read.
+    fn is_unknown_kind(line: &str) -> bool {
+        #[derive(Deserialize)]
+        struct Kind {
+            kind: Option<EntryKind>,
+        }
+        serde_json::from_str::<Kind>(line).is_ok_and(|k| k.kind == Some(EntryKind::Unknown))
     }
 }
 
-pub fn gen_id() -> String {
-    let mut rng = rand::rng();
-    let bytes: [u8; 4] = rng.random();
+/// A non-blank log line that does not parse as a `TaskEntry`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BadLine {
+    /// 1-based line number in `tasks.log`.
+    pub line: usize,
+    pub error: String,
+    pub raw: String,
+}
+
+/// Result of parsing the whole log, keeping track of what was skipped.
+#[derive(Debug, Default)]
+pub struct LogScan {
+    pub entries: Vec<TaskEntry>,
+    pub bad: Vec<BadLine>,
+    /// The final line has no trailing newline and does not parse, which is what
+    /// a writer that crashed mid-append leaves behind.
+    pub truncated: bool,
+    /// Entries of a kind this version does not know, written by a newer one.
+    pub unknown: usize,
+}
+
+impl LogScan {
+    pub fn parse(content: &str) -> Self {
+        Self::parse_from(content, 0)
+    }
+
+    /// Parse a chunk of the log whose first line is line `lines_before + 1`.
+    pub fn parse_from(content: &str, lines_before: usize) -> Self {
+        let mut scan = LogScan::default();
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match TaskEntry::parse(line) {
+                Ok(entry) if entry.kind == Some(EntryKind::Unknown) => scan.unknown += 1,
+                Ok(entry) => scan.entries.push(entry),
+                Err(_) if TaskEntry::is_unknown_kind(line) => scan.unknown += 1,
+                Err(e) => scan.bad.push(BadLine {
+                    line: lines_before + i + 1,
+                    error: e.to_string(),
+                    raw: line.to_string(),
+                }),
+            }
+        }
+        let last_line = lines_before + content.lines().count();
+        scan.truncated =
+            !content.ends_with('\n') && scan.bad.last().is_some_and(|b| b.line == last_line);
+        scan
+    }
+}
+
+/// Shape of newly generated task IDs. Existing IDs of any shape keep working.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IdFormat {
+    /// 8 random hex chars (default)
+    #[default]
+    Hex8,
+    /// 16 random hex chars
+    Hex16,
+    /// 26-char ULID: millisecond timestamp + randomness, sorts by creation time
+    Ulid,
+}
+
+impl std::str::FromStr for IdFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex8" => Ok(Self::Hex8),
+            "hex16" => Ok(Self::Hex16),
+            "ulid" => Ok(Self::Ulid),
+            other => Err(format!(
+                "unknown ID format '{other}' (expected hex8, hex16 or ulid)"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for IdFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Hex8 => "hex8",
+            Self::Hex16 => "hex16",
+            Self::Ulid => "ulid",
+        })
+    }
+}
+
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+fn hex(bytes: &[u8]) -> String {
     bytes.iter().map(|b| format!("{b:02x}")).collect()
 }
 
+fn ulid(millis: u64, random: u128) -> String {
+    let value = (u128::from(millis & 0xffff_ffff_ffff) << 80) | (random & ((1u128 << 80) - 1));
+    (0..26)
+        .rev()
+        .map(|i| CROCKFORD[((value >> (i * 5)) & 0x1f) as usize] as char)
+        .collect()
+}
+
+pub fn gen_id(format: IdFormat) -> String {
+    let mut rng = rand::rng();
+    match format {
+        IdFormat::Hex8 => hex(&rng.random::<[u8; 4]>()),
+        IdFormat::Hex16 => hex(&rng.random::<[u8; 8]>()),
+        IdFormat::Ulid => ulid(Local::now().timestamp_millis().max(0) as u64, rng.random()),
+    }
+}
+
+/// Exclusive advisory lock on `tasks.log`, released when dropped.
+///
+/// Every append goes through a `LogLock` so that whole lines are written while
+/// no other `task` process can write, and read-modify-append sequences (e.g.
+/// `task update`) can hold the lock across the read and the append.
+pub struct LogLock {
+    file: File,
+    tail_checked: bool,
+}
+
+impl LogLock {
+    /// If a crashed writer left a line without its newline, end it so the next
+    /// append starts on a fresh line instead of corrupting a valid entry too.
+    pub fn terminate_partial_line(&mut self) {
+        self.tail_checked = true;
+        let len = self.file.metadata().map(|m| m.len()).unwrap_or(0);
+        if len == 0 {
+            return;
+        }
+        let mut last = [0u8; 1];
+        let ends_with_newline = self.file.seek(SeekFrom::Start(len - 1)).is_ok()
+            && self.file.read_exact(&mut last).is_ok()
+            && last[0] == b'\n';
+        if !ends_with_newline {
+            self.file.write_all(b"\n").expect("Failed to write");
+        }
+    }
+
+    pub fn append(&mut self, entry: &TaskEntry) {
+        if !self.tail_checked {
+            self.terminate_partial_line();
+        }
+        let mut line = entry.to_jsonl();
+        line.push('\n');
+        self.file
+            .write_all(line.as_bytes())
+            .expect("Failed to write");
+    }
+}
+
+impl Drop for LogLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
 pub struct TaskStore {
     path: PathBuf,
+    lock_timeout: Duration,
+    warned: Cell<bool>,
 }
 
 impl TaskStore {
     pub fn new(path: PathBuf) -> Self {
-        Self { path }
+        Self {
+            path,
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+            warned: Cell::new(false),
+        }
     }
 
-    pub fn default_path() -> Self {
-        if let Ok(custom) = env::var("TASK_LOG_PATH") {
-            return Self::new(PathBuf::from(custom));
+    /// Open the configured log (see `config::load`, which also honours
+    /// `TASK_LOG_PATH`), or `~/.local/share/tasks/tasks.log`.
+    pub fn open(configured: Option<PathBuf>) -> Self {
+        let path = configured.unwrap_or_else(|| {
+            let home = env::var("HOME").expect("HOME not set");
+            PathBuf::from(home).join(".local/share/tasks/tasks.log")
+        });
+        let mut store = Self::new(path);
+        if let Some(secs) = env::var("TASK_LOCK_TIMEOUT")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+        {
+            store = store.with_lock_timeout(Duration::from_secs_f64(secs.max(0.0)));
         }
-        let home = env::var("HOME").expect("HOME not set");
-        Self::new(PathBuf::from(home).join(".local/share/tasks/tasks.log"))
+        store
+    }
+
+    /// Generate an ID in this log's format that no task uses yet, in the log
+    /// or in the segments written by `task compact`. Call while holding the
+    /// log lock so the check and the append are atomic.
+    pub fn unused_id(&self) -> Result<String, String> {
+        let format = self.id_format()?.unwrap_or_default();
+        Ok(self.first_unused_id(|| gen_id(format)))
+    }
+
+    fn first_unused_id(&self, mut generate: impl FnMut() -> String) -> String {
+        let snapshot = self.snapshot();
+        let compacted = self.compacted_ids();
+        loop {
+            let id = generate();
+            let lower = id.to_ascii_lowercase();
+            let taken = compacted
+                .iter()
+                .any(|c| c.to_ascii_lowercase().starts_with(&lower));
+            if !taken && resolve_in(&snapshot.tasks, &id).is_err() {
+                return id;
+            }
+        }
+    }
+
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
     }
 
     fn ensure_dir(&self) {
@@ -76,69 +522,463 @@ impl TaskStore {
         }
     }
 
-    pub fn append(&self, entry: &TaskEntry) {
+    /// Acquire the exclusive log lock, waiting up to the configured timeout
+    /// (`TASK_LOCK_TIMEOUT` seconds, default 10).
+    ///
+    /// `task compact` replaces the log while holding the lock on the old file,
+    /// so after acquiring the lock the file is checked to still be the log;
+    /// if it was replaced, the new one is opened and locked instead.
+    pub fn lock(&self) -> Result<LogLock, String> {
         self.ensure_dir();
-        let mut file = fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.path)
-            .expect("Failed to open tasks.log");
-        writeln!(file, "{}", entry.to_jsonl()).expect("Failed to write");
+        let open = || {
+            fs::OpenOptions::new()
+                .create(true)
+                .read(true)
+                .append(true)
+                .open(&self.path)
+                .expect("Failed to open tasks.log")
+        };
+        let mut file = open();
+        let deadline = Instant::now() + self.lock_timeout;
+        loop {
+            match file.try_lock() {
+                Ok(()) if is_same_file(&file, &self.path) => {
+                    return Ok(LogLock {
+                        file,
+                        tail_checked: false,
+                    });
+                }
+                Ok(()) => {
+                    let _ = file.unlock();
+                    file = open();
+                }
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    thread::sleep(LOCK_POLL_INTERVAL);
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(format!(
+                        "timed out after {:.1}s waiting for the lock on {} (another task process is writing; raise TASK_LOCK_TIMEOUT to wait longer)",
+                        self.lock_timeout.as_secs_f64(),
+                        self.path.display()
+                    ));
+                }
+                Err(TryLockError::Error(e)) => {
+                    return Err(format!("failed to lock {}: {e}", self.path.display()));
+                }
+            }
+        }
     }
 
-    pub fn read_entries(&self) -> Vec<TaskEntry> {
+    #[cfg(test)]
+    pub fn append(&self, entry: &TaskEntry) -> Result<(), String> {
+        self.lock()?.append(entry);
+        Ok(())
+    }
+
+    /// Segment that `task compact` moves history finished in `month`
+    /// (`YYYY-MM`) into, e.g. `tasks.2026-09.log` next to `tasks.log`.
+    pub fn segment_path(&self, month: &str) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match self.path.extension() {
+            Some(ext) => format!("{stem}.{month}.{}", ext.to_string_lossy()),
+            None => format!("{stem}.{month}"),
+        };
+        self.path.with_file_name(name)
+    }
+
+    /// Existing segments, oldest month first.
+    pub fn segment_paths(&self) -> Vec<PathBuf> {
+        let Some(dir) = self.path.parent() else {
+            return vec![];
+        };
+        let Ok(read_dir) = fs::read_dir(dir) else {
+            return vec![];
+        };
+        let mut paths: Vec<PathBuf> = read_dir
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| {
+                let name = p.file_name().unwrap_or_default().to_string_lossy();
+                let month = name.split('.').rev().find(|part| is_month(part));
+                month.is_some_and(|m| *p == self.segment_path(m))
+            })
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Every entry moved out of the log by `task compact`, with project
+    /// aliases applied. Only read when a task is not in the live log, and
+    /// when picking a new ID.
+    pub fn read_segments(&self) -> Vec<TaskEntry> {
+        let mut entries: Vec<TaskEntry> = self
+            .segment_paths()
+            .iter()
+            .filter_map(|p| fs::read(p).ok())
+            .flat_map(|bytes| LogScan::parse(&String::from_utf8_lossy(&bytes)).entries)
+            .collect();
+        self.canonicalize(&mut entries);
+        entries
+    }
+
+    /// IDs of the tasks moved into segments by `task compact`, from
+    /// `tasks.log.compacted`, so new IDs can avoid them without parsing every
+    /// segment. Segments written before that index existed are read instead.
+    pub fn compacted_ids(&self) -> BTreeSet<String> {
+        match fs::read(self.sidecar("compacted")) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+            Err(_) => self.read_segments().into_iter().map(|e| e.id).collect(),
+        }
+    }
+
+    /// Record `ids` in the compacted-ID index. Call while holding the lock.
+    pub fn add_compacted_ids<'a>(
+        &self,
+        ids: impl IntoIterator<Item = &'a String>,
+    ) -> Result<(), String> {
+        let mut all = self.compacted_ids();
+        all.extend(ids.into_iter().cloned());
+        self.write_sidecar("compacted", &all)
+    }
+
+    /// Path of a file kept next to the log, e.g. `tasks.log.quarantine`.
+    pub fn sidecar(&self, suffix: &str) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(suffix);
+        self.path.with_file_name(name)
+    }
+
+    pub fn quarantine_path(&self) -> PathBuf {
+        self.sidecar("quarantine")
+    }
+
+    /// Last heartbeat per task ID, from `tasks.log.heartbeats`. Heartbeats are
+    /// liveness signals, not task history, so they stay out of the log.
+    pub fn heartbeats(&self) -> BTreeMap<String, String> {
+        fs::read(self.sidecar("heartbeats"))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// Record a heartbeat for `id` at `ts`. Takes the log lock so concurrent
+    /// heartbeats from different agents don't overwrite each other.
+    pub fn record_heartbeat(&self, id: &str, ts: &str) -> Result<(), String> {
+        let _lock = self.lock()?;
+        let mut beats = self.heartbeats();
+        beats.insert(id.to_string(), ts.to_string());
+        self.write_sidecar("heartbeats", &beats)
+    }
+
+    /// Replace a sidecar file atomically. Call while holding the log lock.
+    fn write_sidecar<T: Serialize>(&self, suffix: &str, value: &T) -> Result<(), String> {
+        let path = self.sidecar(suffix);
+        let tmp = self.sidecar(&format!("{suffix}.tmp.{}", std::process::id()));
+        let json = serde_json::to_vec(value).expect("Failed to serialize sidecar");
+        fs::write(&tmp, json)
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| format!("failed to write {}: {e}", path.display()))
+    }
+
+    /// Old project values mapped to the name they should be shown under, from
+    /// `tasks.log.projects`. The log itself keeps what was recorded.
+    pub fn project_aliases(&self) -> BTreeMap<String, String> {
+        fs::read(self.sidecar("projects"))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// Add `old -> canonical` project aliases, refusing ones that would loop.
+    pub fn add_project_aliases(&self, aliases: &[(String, String)]) -> Result<(), String> {
+        let _lock = self.lock()?;
+        let mut map = self.project_aliases();
+        for (old, canonical) in aliases {
+            if old == canonical {
+                return Err(format!("cannot alias project '{old}' to itself"));
+            }
+            if canonical_project(&map, canonical) == *old {
+                return Err(format!(
+                    "aliasing '{old}' to '{canonical}' would create an alias cycle"
+                ));
+            }
+            map.insert(old.clone(), canonical.clone());
+        }
+        self.write_sidecar("projects", &map)
+    }
+
+    /// Format of new task IDs, from `tasks.log.id_format` (written by
+    /// `task config set id_format`). It lives next to the log rather than in
+    /// a config file so that every agent writing to a shared log uses it.
+    pub fn id_format(&self) -> Result<Option<IdFormat>, String> {
+        let path = self.sidecar("id_format");
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("failed to read {}: {e}", path.display())),
+        };
+        serde_json::from_slice::<String>(&bytes)
+            .map_err(|e| e.to_string())
+            .and_then(|name| name.parse())
+            .map(Some)
+            .map_err(|e| format!("invalid {}: {e}", path.display()))
+    }
+
+    pub fn set_id_format(&self, format: IdFormat) -> Result<(), String> {
+        let _lock = self.lock()?;
+        self.write_sidecar("id_format", &format.to_string())
+    }
+
+    /// Every project value recorded in the log, as written.
+    pub fn recorded_projects(&self) -> BTreeSet<String> {
+        self.scan().entries.into_iter().map(|e| e.project).collect()
+    }
+
+    fn canonicalize(&self, entries: &mut [TaskEntry]) {
+        let aliases = self.project_aliases();
+        if aliases.is_empty() {
+            return;
+        }
+        for entry in entries {
+            entry.project = canonical_project(&aliases, &entry.project);
+        }
+    }
+
+    /// Parse the whole log, reporting malformed lines instead of dropping them.
+    pub fn scan(&self) -> LogScan {
         if !self.path.exists() {
+            return LogScan::default();
+        }
+        let bytes = fs::read(&self.path).expect("Failed to read tasks.log");
+        LogScan::parse(&String::from_utf8_lossy(&bytes))
+    }
+
+    /// Bad lines already moved aside by `task doctor --quarantine`.
+    pub fn quarantined(&self) -> Vec<BadLine> {
+        fs::read_to_string(self.quarantine_path())
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect()
+    }
+
+    /// Bad lines that have not been quarantined yet.
+    pub fn unquarantined(&self, bad: &[BadLine]) -> Vec<BadLine> {
+        if bad.is_empty() {
             return vec![];
         }
-        let content = fs::read_to_string(&self.path).expect("Failed to read tasks.log");
-        content.lines().filter_map(TaskEntry::from_jsonl).collect()
+        let known: HashSet<(usize, String)> = self
+            .quarantined()
+            .into_iter()
+            .map(|b| (b.line, b.raw))
+            .collect();
+        bad.iter()
+            .filter(|b| !known.contains(&(b.line, b.raw.clone())))
+            .cloned()
+            .collect()
+    }
+
+    fn warn_skipped(&self, bad: usize, unknown: usize) {
+        if bad + unknown == 0 || self.warned.replace(true) {
+            return;
+        }
+        if bad > 0 {
+            eprintln!(
+                "warning: skipped {bad} malformed line(s) in {}; run `task doctor` for details",
+                self.path.display()
+            );
+        }
+        if unknown > 0 {
+            eprintln!(
+                "warning: skipped {unknown} entry(ies) of an unknown kind in {}; they were written by a newer version of task",
+                self.path.display()
+            );
+        }
     }
 
+    /// Full parse of every log line, with project aliases applied. Prefer `snapshot()` when only the
+    /// current state of tasks is needed.
+    pub fn read_entries(&self) -> Vec<TaskEntry> {
+        let mut scan = self.scan();
+        self.warn_skipped(self.unquarantined(&scan.bad).len(), scan.unknown);
+        self.canonicalize(&mut scan.entries);
+        scan.entries
+    }
+
+    /// Latest state of every task, with project aliases applied, served from `tasks.log.snapshot` and
+    /// incrementally updated from the log.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut snapshot = snapshot::load(&self.path, &self.sidecar("snapshot"));
+        self.warn_skipped(self.unquarantined(&snapshot.bad).len(), snapshot.unknown);
+        self.canonicalize(&mut snapshot.tasks);
+        snapshot
+    }
+
+    #[cfg(test)]
     pub fn id_exists(&self, id: &str) -> bool {
-        self.read_entries().iter().any(|e| e.id == id)
+        self.snapshot().get(id).is_some()
     }
 
+    #[cfg(test)]
     pub fn latest_entry(&self, id: &str) -> Option<TaskEntry> {
-        self.read_entries().into_iter().rev().find(|e| e.id == id)
+        self.snapshot().get(id).cloned()
+    }
+
+    /// Resolve a full ID or an unambiguous ID prefix, git-style.
+    /// Matching ignores ASCII case so ULIDs can be typed in lowercase.
+    /// Deleted tasks are refused; see [`Self::resolve_any_id`].
+    pub fn resolve_id(&self, prefix: &str) -> Result<String, String> {
+        self.snapshot().resolve(prefix).map(|t| t.id.clone())
+    }
+
+    /// Like [`Self::resolve_id`], but deleted tasks resolve too.
+    pub fn resolve_any_id(&self, prefix: &str) -> Result<String, String> {
+        resolve_in(&self.snapshot().tasks, prefix)
+    }
+
+    /// Resolve an ID among the tasks moved out of the log by `task compact`.
+    pub fn resolve_compacted_id(&self, prefix: &str) -> Result<String, String> {
+        resolve_in(&latest_states(&self.read_segments()), prefix)
     }
 
+    /// Every entry of one task, tombstones and undos included. A task that is
+    /// not in the log is looked for in the segments written by `task compact`.
     pub fn entries_for_id(&self, id: &str) -> Vec<TaskEntry> {
-        self.read_entries()
+        let entries: Vec<TaskEntry> = self
+            .read_entries()
+            .into_iter()
+            .filter(|e| e.id == id)
+            .collect();
+        if !entries.is_empty() {
+            return entries;
+        }
+        self.read_segments()
             .into_iter()
             .filter(|e| e.id == id)
             .collect()
     }
 
+    /// Latest state of every task that is not deleted.
     pub fn current_tasks(
         &self,
         project: Option<&str>,
         status_filter: Option<&str>,
     ) -> Vec<TaskEntry> {
-        let entries = self.read_entries();
-
-        let mut latest: HashMap<String, TaskEntry> = HashMap::new();
-        let mut first_seen: HashMap<String, usize> = HashMap::new();
-        for (i, entry) in entries.into_iter().enumerate() {
-            first_seen.entry(entry.id.clone()).or_insert(i);
-            latest.insert(entry.id.clone(), entry);
-        }
-
-        let mut result: Vec<TaskEntry> = latest.into_values().collect();
-        result.sort_by_key(|e| first_seen.get(&e.id).copied().unwrap_or(usize::MAX));
-
-        result
+        self.snapshot()
+            .tasks
             .into_iter()
+            .filter(|e| !e.deleted)
             .filter(|e| project.is_none_or(|p| e.project == p))
             .filter(|e| status_filter.is_none_or(|s| e.status == s))
             .collect()
     }
 
-    #[cfg(test)]
     pub fn path(&self) -> &PathBuf {
         &self.path
     }
 }
 
+/// Resolve a full ID or an unambiguous ID prefix among `tasks`.
+pub fn resolve_in(tasks: &[TaskEntry], prefix: &str) -> Result<String, String> {
+    if tasks.iter().any(|t| t.id == prefix) {
+        return Ok(prefix.to_string());
+    }
+    let needle = prefix.to_ascii_lowercase();
+    let candidates: Vec<&TaskEntry> = tasks
+        .iter()
+        .filter(|t| !needle.is_empty() && t.id.to_ascii_lowercase().starts_with(&needle))
+        .collect();
+    match candidates.as_slice() {
+        [] => Err(format!("task '{prefix}' not found")),
+        [only] => Ok(only.id.clone()),
+        many => {
+            let listing: Vec<String> = many
+                .iter()
+                .map(|t| format!("  {}  {}", t.id, t.title))
+                .collect();
+            Err(format!(
+                "ambiguous prefix '{prefix}' matches {} tasks:\n{}",
+                many.len(),
+                listing.join("\n")
+            ))
+        }
+    }
+}
+
+/// Latest entry per ID, in first-seen order.
+pub fn latest_states(entries: &[TaskEntry]) -> Vec<TaskEntry> {
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    let mut states: Vec<TaskEntry> = Vec::new();
+    for entry in entries {
+        match index.get(entry.id.as_str()) {
+            Some(&i) => states[i] = entry.clone(),
+            None => {
+                index.insert(&entry.id, states.len());
+                states.push(entry.clone());
+            }
+        }
+    }
+    states
+}
+
+/// Whether `file` is still the file at `path`.
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt as _;
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Elsewhere an open log cannot be replaced, so only removal is detected.
+#[cfg(not(unix))]
+fn is_same_file(_file: &File, path: &Path) -> bool {
+    path.exists()
+}
+
+/// `YYYY-MM`, as used in segment names.
+fn is_month(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() == 7
+        && b[4] == b'-'
+        && b.iter()
+            .enumerate()
+            .all(|(i, c)| i == 4 || c.is_ascii_digit())
+}
+
+/// Replay one task's history, with each undo entry stepping back over the
+/// state before it, so repeated undos keep going back. Comments are not
+/// states and are passed over. Returns the states still in effect, oldest
+/// first; the last one is the current state.
+pub fn effective_history(entries: &[TaskEntry]) -> Vec<&TaskEntry> {
+    let mut states: Vec<&TaskEntry> = Vec::new();
+    for entry in entries {
+        if entry.kind == Some(EntryKind::Comment) {
+            continue;
+        }
+        if entry.kind == Some(EntryKind::Undo) {
+            states.pop();
+            states.pop();
+        }
+        states.push(entry);
+    }
+    states
+}
+
+/// Follow `aliases` from `project` to its canonical name, stopping if the
+/// chain loops back on itself.
+pub fn canonical_project(aliases: &BTreeMap<String, String>, project: &str) -> String {
+    let mut current = project;
+    for _ in 0..aliases.len() {
+        match aliases.get(current) {
+            Some(next) if next != project => current = next,
+            _ => break,
+        }
+    }
+    current.to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -157,8 +997,7 @@ mod tests {
             project: "test/proj".into(),
             status: status.into(),
             title: title.into(),
-            description: String::new(),
-            note: String::new(),
+            ..TaskEntry::default()
         }
     }
 
@@ -166,17 +1005,69 @@ mod tests {
 
     #[test]
     fn gen_id_is_8_char_hex() {
-        let id = gen_id();
+        let id = gen_id(IdFormat::Hex8);
         assert_eq!(id.len(), 8);
         assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
     }
 
     #[test]
     fn gen_id_is_unique() {
-        let ids: HashSet<String> = (0..100).map(|_| gen_id()).collect();
+        let ids: HashSet<String> = (0..100).map(|_| gen_id(IdFormat::Hex8)).collect();
         assert_eq!(ids.len(), 100);
     }
 
+    #[test]
+    fn gen_id_hex16() {
+        let id = gen_id(IdFormat::Hex16);
+        assert_eq!(id.len(), 16);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn gen_id_ulid_is_crockford_base32() {
+        let id = gen_id(IdFormat::Ulid);
+        assert_eq!(id.len(), 26);
+        assert!(id.bytes().all(|b| CROCKFORD.contains(&b)));
+    }
+
+    #[test]
+    fn ulid_encodes_time_first() {
+        assert_eq!(ulid(0, 0), "00000000000000000000000000");
+        assert_eq!(&ulid(1_469_918_176_385, 0)[..10], "01ARYZ6S41");
+        assert!(ulid(1000, u128::MAX) < ulid(1001, 0));
+    }
+
+    #[test]
+    fn id_format_from_str() {
+        assert_eq!("hex8".parse::<IdFormat>().unwrap(), IdFormat::Hex8);
+        assert_eq!("ulid".parse::<IdFormat>().unwrap(), IdFormat::Ulid);
+        assert!("uuid".parse::<IdFormat>().is_err());
+    }
+
+    #[test]
+    fn unused_id_skips_existing_ids() {
+        let (store, _dir) = temp_store();
+        for i in 0..16u8 {
+            store
+                .append(&entry(&format!("{i:x}"), "todo", "T"))
+                .unwrap();
+        }
+        let id = store.unused_id().unwrap();
+        assert!(store.resolve_id(&id).is_err());
+    }
+
+    #[test]
+    fn id_format_is_kept_with_the_log() {
+        let (store, _dir) = temp_store();
+        assert_eq!(store.id_format().unwrap(), None);
+        assert_eq!(store.unused_id().unwrap().len(), 8);
+        store.set_id_format(IdFormat::Ulid).unwrap();
+        assert_eq!(store.id_format().unwrap(), Some(IdFormat::Ulid));
+        assert_eq!(store.unused_id().unwrap().len(), 26);
+        fs::write(store.sidecar("id_format"), "\"uuid\"").unwrap();
+        assert!(store.unused_id().unwrap_err().contains("unknown ID format"));
+    }
+
     // --- TaskEntry JSONL ---
 
     #[test]
@@ -195,6 +1086,8 @@ mod tests {
     #[test]
     fn from_jsonl_roundtrip() {
         let original = TaskEntry {
+            v: SCHEMA_VERSION,
+            kind: Some(EntryKind::Transition),
             ts: "2026-02-22T14:30:00+09:00".into(),
             id: "deadbeef".into(),
             project: "owner/repo".into(),
@@ -202,6 +1095,18 @@ mod tests {
             title: "Something broke".into(),
             description: "need help".into(),
             note: "API issue".into(),
+            tags: vec!["backend".into()],
+            priority: Some(1),
+            depends_on: vec!["cafebabe".into()],
+            parent: Some("0badf00d".into()),
+            claimed_by: Some("codex".into()),
+            lease_until: Some("2026-02-22T15:00:00+09:00".into()),
+            worktree: Some("/src/repo-wt/feature".into()),
+            branch: Some("feature".into()),
+            actor: Some("claude".into()),
+            session: Some("0b5c1e2a".into()),
+            archived: true,
+            deleted: true,
         };
         let json = original.to_jsonl();
         let parsed = TaskEntry::from_jsonl(&json).unwrap();
@@ -225,6 +1130,132 @@ mod tests {
         assert_eq!(parsed.note, "line1\nline2");
     }
 
+    #[test]
+    fn from_jsonl_legacy_line_without_tags() {
+        let line = r#"{"ts":"2026-02-22T14:30:00+09:00","id":"a1","project":"p","status":"todo","title":"T","description":"","note":""}"#;
+        let parsed = TaskEntry::from_jsonl(line).unwrap();
+        assert!(parsed.tags.is_empty());
+        assert!(!parsed.to_jsonl().contains("tags"));
+        assert_eq!(parsed.v, 1);
+        assert_eq!(parsed.kind, None);
+    }
+
+    #[test]
+    fn new_entries_carry_version_and_kind() {
+        let created = TaskEntry::new(
+            "a1".into(),
+            "p".into(),
+            "todo".into(),
+            "T".into(),
+            String::new(),
+            String::new(),
+        );
+        let json = created.to_jsonl();
+        assert!(json.starts_with(r#"{"v":2,"kind":"create","#), "{json}");
+        let next = TaskEntry::from_jsonl(&json)
+            .unwrap()
+            .successor("doing".into(), String::new());
+        assert_eq!(next.kind, Some(EntryKind::Transition));
+        assert_eq!(next.v, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn successor_carries_fields_forward() {
+        let mut e = entry("a1", "todo", "Title");
+        e.description = "desc".into();
+        e.note = "old".into();
+        e.add_tags(["bug".to_string()]);
+        let next = e.successor("doing".into(), String::new());
+        assert_eq!(next.status, "doing");
+        assert_eq!(next.note, "");
+        assert_eq!(next.title, "Title");
+        assert_eq!(next.description, "desc");
+        assert_eq!(next.tags, vec!["bug"]);
+    }
+
+    #[test]
+    fn tags_add_remove_and_match() {
+        let mut e = entry("a1", "todo", "T");
+        e.add_tags(["backend", " urgent ", "", "backend"].map(String::from));
+        assert_eq!(e.tags, vec!["backend", "urgent"]);
+        assert!(e.has_tags(&["urgent".into(), "backend".into()]));
+        assert!(!e.has_tags(&["frontend".into()]));
+        e.remove_tags(&["backend".into()]);
+        assert_eq!(e.tags, vec!["urgent"]);
+    }
+
+    #[test]
+    fn changes_from_lists_edited_fields() {
+        let mut prev = entry("a1", "todo", "Fxi login");
+        prev.add_tags(["bug".to_string()]);
+        let mut next = prev.successor("doing".into(), "started".into());
+        assert!(next.changes_from(&prev).is_empty());
+        next.title = "Fix login".into();
+        next.project = "other/proj".into();
+        next.description = "details".into();
+        next.priority = Some(1);
+        next.remove_tags(&["bug".into()]);
+        next.add_tags(["auth".to_string()]);
+        assert_eq!(
+            next.changes_from(&prev),
+            vec![
+                "title: Fxi login -> Fix login",
+                "project: test/proj -> other/proj",
+                "description: updated",
+                "priority: none -> p1",
+                "tags: +auth -bug",
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_priority_accepts_p_prefix_and_numbers() {
+        assert_eq!(parse_priority("p0").unwrap(), 0);
+        assert_eq!(parse_priority("P2").unwrap(), 2);
+        assert_eq!(parse_priority("3").unwrap(), 3);
+        assert!(parse_priority("p10").is_err());
+        assert!(parse_priority("high").is_err());
+    }
+
+    #[test]
+    fn priority_rank_puts_unset_last() {
+        let mut tasks = [
+            entry("none", "todo", "N"),
+            TaskEntry {
+                priority: Some(2),
+                ..entry("p2", "todo", "B")
+            },
+            TaskEntry {
+                priority: Some(0),
+                ..entry("p0", "todo", "A")
+            },
+            TaskEntry {
+                priority: Some(2),
+                ..entry("p2b", "todo", "C")
+            },
+        ];
+        tasks.sort_by_key(TaskEntry::priority_rank);
+        let ids: Vec<&str> = tasks.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, vec!["p0", "p2", "p2b", "none"]);
+    }
+
+    #[test]
+    fn live_claim_requires_unexpired_lease() {
+        let now = Local::now();
+        let mut e = entry("a1", "in_progress", "T");
+        assert_eq!(e.live_claim(now), None);
+        e.claimed_by = Some("claude".into());
+        e.lease_until = Some(crate::time::format_ts(now + chrono::Duration::minutes(5)));
+        assert_eq!(e.live_claim(now), Some("claude"));
+        assert!(!e.lease_expired(now));
+        e.lease_until = Some(crate::time::format_ts(now - chrono::Duration::minutes(5)));
+        assert_eq!(e.live_claim(now), None);
+        assert!(e.lease_expired(now));
+        e.release();
+        assert!(e.claimed_by.is_none() && e.lease_until.is_none());
+        assert!(!e.lease_expired(now));
+    }
+
     #[test]
     fn new_sets_timestamp() {
         let e = TaskEntry::new(
@@ -245,7 +1276,7 @@ mod tests {
     fn append_and_read() {
         let (store, _dir) = temp_store();
         let e = entry("aabbccdd", "todo", "First task");
-        store.append(&e);
+        store.append(&e).unwrap();
         let entries = store.read_entries();
         assert_eq!(entries.len(), 1);
         assert_eq!(entries[0].id, "aabbccdd");
@@ -255,7 +1286,9 @@ mod tests {
     fn append_is_additive() {
         let (store, _dir) = temp_store();
         for i in 0..3 {
-            store.append(&entry(&format!("id{i:07}"), "todo", &format!("Task {i}")));
+            store
+                .append(&entry(&format!("id{i:07}"), "todo", &format!("Task {i}")))
+                .unwrap();
         }
         assert_eq!(store.read_entries().len(), 3);
     }
@@ -264,7 +1297,7 @@ mod tests {
     fn id_exists_check() {
         let (store, _dir) = temp_store();
         assert!(!store.id_exists("nonexist"));
-        store.append(&entry("exist123", "todo", "T"));
+        store.append(&entry("exist123", "todo", "T")).unwrap();
         assert!(store.id_exists("exist123"));
         assert!(!store.id_exists("other456"));
     }
@@ -272,10 +1305,12 @@ mod tests {
     #[test]
     fn latest_entry_tracks_updates() {
         let (store, _dir) = temp_store();
-        store.append(&entry("aabb0011", "todo", "Original"));
+        store
+            .append(&entry("aabb0011", "todo", "Original"))
+            .unwrap();
         let mut e2 = entry("aabb0011", "doing", "Original");
         e2.note = "started".into();
-        store.append(&e2);
+        store.append(&e2).unwrap();
         let latest = store.latest_entry("aabb0011").unwrap();
         assert_eq!(latest.status, "doing");
         assert_eq!(latest.note, "started");
@@ -287,13 +1322,62 @@ mod tests {
         assert!(store.latest_entry("nope").is_none());
     }
 
+    #[test]
+    fn resolve_id_exact_and_prefix() {
+        let (store, _dir) = temp_store();
+        store.append(&entry("a3f8c2d1", "todo", "A")).unwrap();
+        store.append(&entry("b7e1d4f2", "todo", "B")).unwrap();
+        assert_eq!(store.resolve_id("a3f8c2d1").unwrap(), "a3f8c2d1");
+        assert_eq!(store.resolve_id("a3f8").unwrap(), "a3f8c2d1");
+        assert_eq!(store.resolve_id("b").unwrap(), "b7e1d4f2");
+    }
+
+    #[test]
+    fn resolve_id_not_found() {
+        let (store, _dir) = temp_store();
+        store.append(&entry("a3f8c2d1", "todo", "A")).unwrap();
+        assert!(store.resolve_id("ff").unwrap_err().contains("not found"));
+        assert!(store.resolve_id("").unwrap_err().contains("not found"));
+    }
+
+    #[test]
+    fn resolve_id_ambiguous_lists_candidates() {
+        let (store, _dir) = temp_store();
+        store.append(&entry("a3f8c2d1", "todo", "Auth")).unwrap();
+        store.append(&entry("a3000000", "todo", "Bug")).unwrap();
+        let err = store.resolve_id("a3").unwrap_err();
+        assert!(err.contains("ambiguous prefix 'a3'"));
+        assert!(err.contains("a3f8c2d1  Auth"));
+        assert!(err.contains("a3000000  Bug"));
+    }
+
+    #[test]
+    fn resolve_id_ignores_case() {
+        let (store, _dir) = temp_store();
+        store
+            .append(&entry("01ARYZ6S41TSV4RRFFQ69G5FAV", "todo", "U"))
+            .unwrap();
+        assert_eq!(
+            store.resolve_id("01aryz6s").unwrap(),
+            "01ARYZ6S41TSV4RRFFQ69G5FAV"
+        );
+    }
+
+    #[test]
+    fn resolve_id_exact_match_wins_over_longer_ids() {
+        let (store, _dir) = temp_store();
+        store.append(&entry("abc", "todo", "Short")).unwrap();
+        store.append(&entry("abcdef01", "todo", "Long")).unwrap();
+        assert_eq!(store.resolve_id("abc").unwrap(), "abc");
+    }
+
     #[test]
     fn entries_for_id_returns_history() {
         let (store, _dir) = temp_store();
-        store.append(&entry("t1", "todo", "A"));
-        store.append(&entry("t1", "doing", "A"));
-        store.append(&entry("t2", "todo", "B"));
-        store.append(&entry("t1", "blocked", "A"));
+        store.append(&entry("t1", "todo", "A")).unwrap();
+        store.append(&entry("t1", "doing", "A")).unwrap();
+        store.append(&entry("t2", "todo", "B")).unwrap();
+        store.append(&entry("t1", "blocked", "A")).unwrap();
         let history = store.entries_for_id("t1");
         assert_eq!(history.len(), 3);
         assert_eq!(history[0].status, "todo");
@@ -304,8 +1388,8 @@ mod tests {
     #[test]
     fn current_tasks_deduplicates() {
         let (store, _dir) = temp_store();
-        store.append(&entry("task0001", "todo", "A"));
-        store.append(&entry("task0001", "doing", "A"));
+        store.append(&entry("task0001", "todo", "A")).unwrap();
+        store.append(&entry("task0001", "doing", "A")).unwrap();
         let tasks = store.current_tasks(None, None);
         assert_eq!(tasks.len(), 1);
         assert_eq!(tasks[0].status, "doing");
@@ -314,8 +1398,8 @@ mod tests {
     #[test]
     fn current_tasks_filters_by_status() {
         let (store, _dir) = temp_store();
-        store.append(&entry("t1", "todo", "A"));
-        store.append(&entry("t2", "doing", "B"));
+        store.append(&entry("t1", "todo", "A")).unwrap();
+        store.append(&entry("t2", "doing", "B")).unwrap();
         assert_eq!(store.current_tasks(None, Some("todo")).len(), 1);
         assert_eq!(store.current_tasks(None, Some("todo"))[0].id, "t1");
     }
@@ -323,20 +1407,67 @@ mod tests {
     #[test]
     fn current_tasks_filters_by_project() {
         let (store, _dir) = temp_store();
-        store.append(&entry("t1", "todo", "A"));
+        store.append(&entry("t1", "todo", "A")).unwrap();
         let mut e2 = entry("t2", "todo", "B");
         e2.project = "other/proj".into();
-        store.append(&e2);
+        store.append(&e2).unwrap();
         assert_eq!(store.current_tasks(Some("test/proj"), None).len(), 1);
         assert_eq!(store.current_tasks(Some("other/proj"), None).len(), 1);
         assert_eq!(store.current_tasks(None, None).len(), 2);
     }
 
+    #[test]
+    fn deleted_tasks_are_hidden_and_refused() {
+        let (store, _dir) = temp_store();
+        store.append(&entry("t1", "todo", "A")).unwrap();
+        store.append(&entry("t2", "todo", "B")).unwrap();
+        let mut tombstone = entry("t1", "todo", "A");
+        tombstone.deleted = true;
+        store.append(&tombstone).unwrap();
+        let ids: Vec<String> = store
+            .current_tasks(None, None)
+            .into_iter()
+            .map(|t| t.id)
+            .collect();
+        assert_eq!(ids, vec!["t2"]);
+        assert!(store.resolve_id("t1").unwrap_err().contains("task undo t1"));
+        assert_eq!(store.resolve_any_id("t1").unwrap(), "t1");
+        assert_eq!(store.entries_for_id("t1").len(), 2);
+    }
+
+    #[test]
+    fn effective_history_steps_back_on_each_undo_and_skips_comments() {
+        let undo = |status: &str| TaskEntry {
+            kind: Some(EntryKind::Undo),
+            ..entry("t1", status, "A")
+        };
+        let entries = [
+            entry("t1", "todo", "A"),
+            entry("t1", "doing", "A"),
+            entry("t1", "blocked", "A"),
+            undo("doing"),
+            undo("todo"),
+            entry("t1", "review", "A"),
+            entry("t1", "review", "A").comment("looks good".into()),
+        ];
+        let statuses = |n: usize| -> Vec<String> {
+            effective_history(&entries[..n])
+                .iter()
+                .map(|e| e.status.clone())
+                .collect()
+        };
+        assert_eq!(statuses(3), vec!["todo", "doing", "blocked"]);
+        assert_eq!(statuses(4), vec!["todo", "doing"]);
+        assert_eq!(statuses(5), vec!["todo"]);
+        assert_eq!(statuses(6), vec!["todo", "review"]);
+        assert_eq!(statuses(7), vec!["todo", "review"]);
+    }
+
     #[test]
     fn current_tasks_preserves_insertion_order() {
         let (store, _dir) = temp_store();
         for id in ["c", "a", "b"] {
-            store.append(&entry(id, "todo", id));
+            store.append(&entry(id, "todo", id)).unwrap();
         }
         let tasks = store.current_tasks(None, None);
         let ids: Vec<&str> = tasks.iter().map(|t| t.id.as_str()).collect();
@@ -354,19 +1485,276 @@ mod tests {
     #[test]
     fn append_only_file_grows() {
         let (store, _dir) = temp_store();
-        store.append(&entry("x", "todo", "T"));
-        store.append(&entry("x", "doing", "T"));
+        store.append(&entry("x", "todo", "T")).unwrap();
+        store.append(&entry("x", "doing", "T")).unwrap();
         let raw = fs::read_to_string(store.path()).unwrap();
         assert_eq!(raw.lines().count(), 2);
     }
 
+    #[test]
+    fn scan_reports_bad_lines_with_numbers() {
+        let good = entry("a1", "todo", "A").to_jsonl();
+        let content = format!("{good}\nnot json\n\n{good}\n{{\"id\":\"x\"}}\n");
+        let scan = LogScan::parse(&content);
+        assert_eq!(scan.entries.len(), 2);
+        let lines: Vec<usize> = scan.bad.iter().map(|b| b.line).collect();
+        assert_eq!(lines, vec![2, 5]);
+        assert!(scan.bad[1].error.contains("missing field"));
+        assert!(!scan.truncated);
+    }
+
+    #[test]
+    fn scan_skips_unknown_kinds_without_calling_them_bad() {
+        let good = entry("a1", "todo", "A").to_jsonl();
+        let future = good.replace(r#""v":0"#, r#""v":9,"kind":"reaction","emoji":"+1""#);
+        let sparse = r#"{"v":9,"kind":"reaction","id":"a1"}"#;
+        let scan = LogScan::parse(&format!("{good}\n{future}\n{sparse}\n"));
+        assert_eq!(scan.entries.len(), 1);
+        assert_eq!(scan.unknown, 2);
+        assert!(scan.bad.is_empty());
+    }
+
+    #[test]
+    fn scan_detects_truncated_final_line() {
+        let good = entry("a1", "todo", "A").to_jsonl();
+        let content = format!("{good}\n{}", &good[..good.len() / 2]);
+        let scan = LogScan::parse(&content);
+        assert!(scan.truncated);
+        assert_eq!(scan.bad.len(), 1);
+        assert_eq!(scan.bad[0].line, 2);
+    }
+
+    #[test]
+    fn scan_detects_truncated_final_line_of_a_later_chunk() {
+        let good = entry("a1", "todo", "A").to_jsonl();
+        let content = format!("{good}\n{}", &good[..good.len() / 2]);
+        let scan = LogScan::parse_from(&content, 10);
+        assert!(scan.truncated);
+        assert_eq!(scan.bad[0].line, 12);
+    }
+
+    #[test]
+    fn append_after_truncated_line_starts_fresh_line() {
+        let (store, _dir) = temp_store();
+        let good = entry("a1", "todo", "A").to_jsonl();
+        fs::write(store.path(), format!("{good}\n{{\"ts\":\"2026")).unwrap();
+        store.append(&entry("a2", "todo", "B")).unwrap();
+        let scan = store.scan();
+        assert_eq!(scan.entries.len(), 2);
+        assert_eq!(scan.bad.len(), 1);
+        assert!(!scan.truncated);
+    }
+
+    #[test]
+    fn quarantined_lines_are_not_reported_again() {
+        let (store, _dir) = temp_store();
+        fs::write(store.path(), "garbage\n").unwrap();
+        let bad = store.scan().bad;
+        assert_eq!(store.unquarantined(&bad).len(), 1);
+        let line = serde_json::to_string(&bad[0]).unwrap();
+        fs::write(store.quarantine_path(), format!("{line}\n")).unwrap();
+        assert!(store.unquarantined(&bad).is_empty());
+    }
+
+    #[test]
+    fn project_aliases_apply_on_read_only() {
+        let (store, _dir) = temp_store();
+        let mut old = entry("t1", "todo", "A");
+        old.project = "/old/path".into();
+        store.append(&old).unwrap();
+        store
+            .add_project_aliases(&[("/old/path".into(), "owner/repo".into())])
+            .unwrap();
+        assert_eq!(store.current_tasks(Some("owner/repo"), None).len(), 1);
+        assert_eq!(store.entries_for_id("t1")[0].project, "owner/repo");
+        assert_eq!(
+            store.recorded_projects().into_iter().collect::<Vec<_>>(),
+            vec!["/old/path"]
+        );
+        assert!(
+            fs::read_to_string(store.path())
+                .unwrap()
+                .contains("/old/path")
+        );
+    }
+
+    #[test]
+    fn project_aliases_reject_cycles() {
+        let (store, _dir) = temp_store();
+        store
+            .add_project_aliases(&[("a".into(), "b".into()), ("b".into(), "c".into())])
+            .unwrap();
+        let err = store
+            .add_project_aliases(&[("c".into(), "a".into())])
+            .unwrap_err();
+        assert!(err.contains("alias cycle"));
+        assert!(
+            store
+                .add_project_aliases(&[("x".into(), "x".into())])
+                .is_err()
+        );
+        assert_eq!(canonical_project(&store.project_aliases(), "a"), "c");
+        assert_eq!(canonical_project(&store.project_aliases(), "z"), "z");
+    }
+
+    #[test]
+    fn heartbeats_are_recorded_per_task() {
+        let (store, _dir) = temp_store();
+        assert!(store.heartbeats().is_empty());
+        store
+            .record_heartbeat("a1", "2026-02-22T14:30:00+09:00")
+            .unwrap();
+        store
+            .record_heartbeat("b2", "2026-02-22T14:31:00+09:00")
+            .unwrap();
+        store
+            .record_heartbeat("a1", "2026-02-22T14:32:00+09:00")
+            .unwrap();
+        let beats = store.heartbeats();
+        assert_eq!(beats.len(), 2);
+        assert_eq!(beats["a1"], "2026-02-22T14:32:00+09:00");
+        assert!(store.read_entries().is_empty());
+    }
+
+    #[test]
+    fn lock_times_out_while_held() {
+        let (store, _dir) = temp_store();
+        let store = store.with_lock_timeout(Duration::from_millis(50));
+        let _held = store.lock().unwrap();
+        let err = store.lock().err().expect("second lock should time out");
+        assert!(err.contains("timed out"));
+        assert!(store.append(&entry("x", "todo", "T")).is_err());
+    }
+
+    #[test]
+    fn lock_released_on_drop() {
+        let (store, _dir) = temp_store();
+        let store = store.with_lock_timeout(Duration::from_millis(50));
+        {
+            let mut lock = store.lock().unwrap();
+            lock.append(&entry("x", "todo", "T"));
+        }
+        store.append(&entry("x", "doing", "T")).unwrap();
+        assert_eq!(store.entries_for_id("x").len(), 2);
+    }
+
+    #[test]
+    fn lock_follows_a_replaced_log() {
+        let (store, _dir) = temp_store();
+        let path = store.path().clone();
+        store.append(&entry("old", "done", "T")).unwrap();
+        let held = store.lock().unwrap();
+        let waiter = {
+            let path = path.clone();
+            thread::spawn(move || {
+                TaskStore::new(path)
+                    .append(&entry("new", "todo", "T"))
+                    .unwrap();
+            })
+        };
+        // Let the waiter open the old file before it is replaced.
+        thread::sleep(Duration::from_millis(100));
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, format!("{}\n", entry("kept", "todo", "T").to_jsonl())).unwrap();
+        fs::rename(&tmp, &path).unwrap();
+        drop(held);
+        waiter.join().unwrap();
+        let ids: Vec<String> = store.read_entries().into_iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec!["kept", "new"]);
+    }
+
+    #[test]
+    fn segments_are_named_by_month_next_to_the_log() {
+        let (store, dir) = temp_store();
+        assert_eq!(
+            store.segment_path("2026-09"),
+            dir.path().join("tasks.2026-09.log")
+        );
+        for name in [
+            "tasks.2026-10.log",
+            "tasks.2026-09.log",
+            "tasks.log.snapshot",
+            "other.2026-09.log",
+            "tasks.2026-9.log",
+        ] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        fs::write(
+            dir.path().join("tasks.2026-09.log"),
+            format!("{}\n", entry("gone", "done", "T").to_jsonl()),
+        )
+        .unwrap();
+        assert_eq!(
+            store.segment_paths(),
+            vec![
+                dir.path().join("tasks.2026-09.log"),
+                dir.path().join("tasks.2026-10.log")
+            ]
+        );
+        assert_eq!(store.read_segments().len(), 1);
+        assert_eq!(store.resolve_compacted_id("go").unwrap(), "gone");
+        assert!(store.resolve_id("gone").is_err());
+        assert_eq!(store.entries_for_id("gone").len(), 1);
+    }
+
+    #[test]
+    fn new_ids_skip_compacted_ids() {
+        let (store, dir) = temp_store();
+        store.append(&entry("live", "todo", "T")).unwrap();
+        fs::write(
+            dir.path().join("tasks.2026-09.log"),
+            format!("{}\n", entry("gone", "done", "T").to_jsonl()),
+        )
+        .unwrap();
+        let mut candidates = ["live", "gone", "fresh"].into_iter();
+        let id = store.first_unused_id(|| candidates.next().unwrap().to_string());
+        assert_eq!(id, "fresh");
+
+        // Once compact keeps the index, it is checked instead of the segments.
+        store.add_compacted_ids(&["indexed".to_string()]).unwrap();
+        assert_eq!(
+            store.compacted_ids(),
+            BTreeSet::from(["gone".to_string(), "indexed".to_string()])
+        );
+        let mut candidates = ["gone", "INDEX", "fresh"].into_iter();
+        let id = store.first_unused_id(|| candidates.next().unwrap().to_string());
+        assert_eq!(id, "fresh");
+    }
+
+    #[test]
+    fn concurrent_appends_do_not_interleave() {
+        let (store, _dir) = temp_store();
+        let path = store.path().clone();
+        let note = "n".repeat(256 * 1024);
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                let note = note.clone();
+                thread::spawn(move || {
+                    let store = TaskStore::new(path);
+                    for j in 0..5 {
+                        let mut e = entry(&format!("t{i}-{j}"), "todo", "T");
+                        e.note = note.clone();
+                        store.append(&e).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        let raw = fs::read_to_string(&path).unwrap();
+        assert_eq!(raw.lines().count(), 40);
+        assert_eq!(store.read_entries().len(), 40);
+    }
+
     #[test]
     fn stored_as_valid_jsonl() {
         let (store, _dir) = temp_store();
         let mut e = entry("a1b2c3d4", "todo", "Test");
         e.description = "desc".into();
         e.note = "note".into();
-        store.append(&e);
+        store.append(&e).unwrap();
         let raw = fs::read_to_string(store.path()).unwrap();
         let parsed: serde_json::Value = serde_json::from_str(raw.trim()).unwrap();
         assert_eq!(parsed["id"], "a1b2c3d4");
@@ -0,0 +1,164 @@
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, TimeZone as _};
+
+/// Timestamp format used for `ts` and every other time stored in the log.
+pub fn format_ts(time: DateTime<Local>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%:z").to_string()
+}
+
+pub fn now_ts() -> String {
+    format_ts(Local::now())
+}
+
+pub fn parse_ts(ts: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(ts).ok()
+}
+
+/// Parse durations like `30s`, `30m`, `2h`, `1d`, `2w` or `1h30m`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration '{s}' (expected e.g. 30m, 2h, 1d, 1h30m)");
+    let out_of_range = || format!("duration '{s}' out of range");
+    let mut total = Duration::zero();
+    let mut digits = String::new();
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let n: i64 = digits.parse().map_err(|_| out_of_range())?;
+        digits.clear();
+        let part = match c {
+            's' => Duration::try_seconds(n),
+            'm' => Duration::try_minutes(n),
+            'h' => Duration::try_hours(n),
+            'd' => Duration::try_days(n),
+            'w' => Duration::try_weeks(n),
+            _ => return Err(invalid()),
+        };
+        total = part
+            .and_then(|part| total.checked_add(&part))
+            .ok_or_else(out_of_range)?;
+    }
+    if !digits.is_empty() || s.trim().is_empty() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+/// The time `ago` before now, unless that is before the earliest time chrono
+/// can represent.
+pub fn before_now(ago: Duration) -> Result<DateTime<Local>, String> {
+    Local::now()
+        .checked_sub_signed(ago)
+        .ok_or_else(|| format!("{} ago is out of range", format_duration(ago)))
+}
+
+/// Parse a point in time for `--since` / `--until`: a duration back from now
+/// (`24h`, `7d`), a date (`2026-02-22`, local midnight) or an RFC 3339
+/// timestamp.
+pub fn parse_time(s: &str) -> Result<DateTime<FixedOffset>, String> {
+    if let Ok(ago) = parse_duration(s) {
+        return before_now(ago).map(|time| time.fixed_offset());
+    }
+    if let Some(ts) = parse_ts(s) {
+        return Ok(ts);
+    }
+    NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+        .ok()
+        .and_then(|date| {
+            Local
+                .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+                .earliest()
+        })
+        .map(|time| time.fixed_offset())
+        .ok_or_else(|| {
+            format!(
+                "invalid time '{s}' (expected e.g. 24h, 7d, 2026-02-22 or an RFC 3339 timestamp)"
+            )
+        })
+}
+
+/// Compact human form of a duration, e.g. `2d3h`, `3h12m`, `45m`, `30s`.
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.num_seconds().max(0);
+    let (days, hours, mins) = (secs / 86_400, secs % 86_400 / 3600, secs % 3600 / 60);
+    match (days, hours, mins) {
+        (0, 0, 0) => format!("{secs}s"),
+        (0, 0, m) => format!("{m}m"),
+        (0, h, m) => format!("{h}h{m}m"),
+        (d, h, _) => format!("{d}d{h}h"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_duration_compact() {
+        assert_eq!(format_duration(Duration::seconds(30)), "30s");
+        assert_eq!(format_duration(Duration::minutes(45)), "45m");
+        assert_eq!(format_duration(Duration::minutes(192)), "3h12m");
+        assert_eq!(format_duration(Duration::hours(51)), "2d3h");
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("45s").unwrap(), Duration::seconds(45));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::minutes(30));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::hours(2));
+        assert_eq!(parse_duration("30d").unwrap(), Duration::days(30));
+        assert_eq!(parse_duration("1w").unwrap(), Duration::weeks(1));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_duration("0s").unwrap(), Duration::zero());
+    }
+
+    #[test]
+    fn parse_duration_rejects_garbage() {
+        for bad in ["", "30", "m", "2x", "1.5h", "-1h"] {
+            assert!(parse_duration(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn parse_duration_rejects_out_of_range() {
+        for huge in [
+            "99999999999999999d",
+            "99999999999999999999s",
+            "9999999999999w1w",
+        ] {
+            let err = parse_duration(huge).unwrap_err();
+            assert!(err.contains("out of range"), "{huge}: {err}");
+        }
+    }
+
+    #[test]
+    fn parse_time_forms() {
+        let ago = Local::now().fixed_offset() - parse_time("24h").unwrap();
+        assert!((ago - Duration::hours(24)).num_seconds().abs() < 5);
+        assert_eq!(
+            parse_time("2026-02-22T14:30:00+09:00").unwrap(),
+            parse_ts("2026-02-22T14:30:00+09:00").unwrap()
+        );
+        let midnight = parse_time("2026-02-22").unwrap().with_timezone(&Local);
+        assert_eq!(
+            midnight.format("%Y-%m-%d %H:%M").to_string(),
+            "2026-02-22 00:00"
+        );
+        assert!(parse_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn before_now_rejects_out_of_range() {
+        assert!(before_now(Duration::days(1)).is_ok());
+        let err = parse_time("5000000000d").unwrap_err();
+        assert!(err.contains("out of range"), "{err}");
+    }
+
+    #[test]
+    fn ts_roundtrip() {
+        let ts = now_ts();
+        let parsed = parse_ts(&ts).unwrap();
+        assert_eq!(format_ts(parsed.with_timezone(&Local)), ts);
+        assert!(parse_ts("yesterday").is_none());
+    }
+}
@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Optional status workflow from the `[workflow]` table of a config file.
+///
+/// ```toml
+/// [workflow]
+/// statuses = ["inbox", "todo", "doing", "blocked", "inreview", "done"]
+///
+/// [workflow.aliases]
+/// wip = "doing"
+///
+/// [workflow.transitions]
+/// todo = ["doing", "done"]
+/// doing = ["blocked", "inreview", "done"]
+/// ```
+///
+/// An empty `statuses` list allows any status, and a status missing from
+/// `transitions` may move to any status.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Workflow {
+    #[serde(default)]
+    pub statuses: Vec<String>,
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+    #[serde(default)]
+    pub transitions: BTreeMap<String, Vec<String>>,
+}
+
+const FORCE_HINT: &str = "use --force to override";
+
+impl Workflow {
+    /// Map an alias to its status, leaving other names untouched.
+    pub fn canonical(&self, status: &str) -> String {
+        self.aliases
+            .get(status)
+            .cloned()
+            .unwrap_or_else(|| status.to_string())
+    }
+
+    /// Resolve aliases and check the result is a declared status.
+    pub fn resolve_status(&self, status: &str) -> Result<String, String> {
+        let status = self.canonical(status);
+        if self.statuses.is_empty() || self.statuses.contains(&status) {
+            return Ok(status);
+        }
+        let suggestion = self
+            .statuses
+            .iter()
+            .chain(self.aliases.keys())
+            .map(|s| (edit_distance(&status, s), s))
+            .filter(|(d, _)| *d <= 2)
+            .min_by_key(|(d, _)| *d)
+            .map(|(_, s)| format!(" (did you mean '{s}'?)"))
+            .unwrap_or_default();
+        Err(format!(
+            "unknown status '{status}'{suggestion}; allowed: {}; {FORCE_HINT}",
+            self.statuses.join(", ")
+        ))
+    }
+
+    /// Resolve a status that `command` writes on its own (`doing` for
+    /// `task claim`, `blocked` for `task stale --block`). A workflow that
+    /// calls it something else must alias it, e.g. `doing = "in_progress"`.
+    pub fn resolve_builtin(&self, status: &str, command: &str) -> Result<String, String> {
+        self.resolve_status(status).map_err(|_| {
+            format!(
+                "`{command}` moves tasks to '{status}', which the workflow does not allow; \
+                 add it to statuses or alias it under [workflow.aliases] \
+                 (e.g. {status} = \"my_status\")"
+            )
+        })
+    }
+
+    /// Staying in the same status (e.g. to add a note) is always allowed.
+    pub fn check_transition(&self, from: &str, to: &str) -> Result<(), String> {
+        match self.transitions.get(from) {
+            Some(allowed) if from != to && !allowed.iter().any(|s| s == to) => Err(format!(
+                "transition '{from}' -> '{to}' is not allowed (from {from}: {}); {FORCE_HINT}",
+                allowed.join(", ")
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Levenshtein distance, for "did you mean" suggestions.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workflow() -> Workflow {
+        toml::from_str(
+            r#"
+            statuses = ["todo", "doing", "blocked", "inreview", "done"]
+            [aliases]
+            wip = "doing"
+            [transitions]
+            todo = ["doing", "done"]
+            doing = ["blocked", "inreview", "done"]
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn empty_workflow_allows_anything() {
+        let wf = Workflow::default();
+        assert_eq!(wf.resolve_status("whatever").unwrap(), "whatever");
+        assert!(wf.check_transition("done", "todo").is_ok());
+    }
+
+    #[test]
+    fn aliases_resolve() {
+        assert_eq!(workflow().resolve_status("wip").unwrap(), "doing");
+        assert_eq!(workflow().canonical("todo"), "todo");
+    }
+
+    #[test]
+    fn unknown_status_suggests_closest() {
+        let err = workflow().resolve_status("inreivew").unwrap_err();
+        assert!(err.contains("unknown status 'inreivew'"));
+        assert!(err.contains("did you mean 'inreview'?"));
+        assert!(err.contains("--force"));
+        let err = workflow().resolve_status("zzzzzzzz").unwrap_err();
+        assert!(!err.contains("did you mean"));
+    }
+
+    #[test]
+    fn builtin_statuses_resolve_through_aliases() {
+        assert_eq!(
+            Workflow::default()
+                .resolve_builtin("doing", "task claim")
+                .unwrap(),
+            "doing"
+        );
+        assert_eq!(
+            workflow().resolve_builtin("blocked", "task claim").unwrap(),
+            "blocked"
+        );
+        let mut wf = workflow();
+        wf.statuses = vec!["todo".into(), "in_progress".into(), "done".into()];
+        let err = wf.resolve_builtin("doing", "task claim").unwrap_err();
+        assert!(err.contains("`task claim` moves tasks to 'doing'"));
+        wf.aliases.insert("doing".into(), "in_progress".into());
+        assert_eq!(
+            wf.resolve_builtin("doing", "task claim").unwrap(),
+            "in_progress"
+        );
+    }
+
+    #[test]
+    fn transitions_are_enforced() {
+        let wf = workflow();
+        assert!(wf.check_transition("todo", "doing").is_ok());
+        assert!(wf.check_transition("doing", "doing").is_ok());
+        assert!(wf.check_transition("blocked", "todo").is_ok());
+        let err = wf.check_transition("todo", "inreview").unwrap_err();
+        assert!(err.contains("'todo' -> 'inreview' is not allowed"));
+        assert!(err.contains("from todo: doing, done"));
+    }
+
+    #[test]
+    fn edit_distance_basics() {
+        assert_eq!(edit_distance("inreivew", "inreview"), 2);
+        assert_eq!(edit_distance("doing", "doing"), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+}
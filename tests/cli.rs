@@ -5,14 +5,16 @@ fn task_cmd_with_log() -> (assert_cmd::Command, tempfile::TempDir) {
     let dir = tempfile::tempdir().unwrap();
     let log_path = dir.path().join("tasks.log");
     let mut cmd = cargo_bin_cmd!("task");
-    cmd.env("TASK_LOG_PATH", log_path.to_str().unwrap());
+    cmd.env("TASK_LOG_PATH", log_path.to_str().unwrap())
+        .env("XDG_CONFIG_HOME", dir.path().join("config"));
     (cmd, dir)
 }
 
 fn task_cmd_env(dir: &tempfile::TempDir) -> assert_cmd::Command {
     let log_path = dir.path().join("tasks.log");
     let mut cmd = cargo_bin_cmd!("task");
-    cmd.env("TASK_LOG_PATH", log_path.to_str().unwrap());
+    cmd.env("TASK_LOG_PATH", log_path.to_str().unwrap())
+        .env("XDG_CONFIG_HOME", dir.path().join("config"));
     cmd
 }
 
@@ -23,6 +25,21 @@ fn created_id(stdout: &str) -> &str {
         .expect("TASK_ADD_ line not found")
 }
 
+fn create_task(dir: &tempfile::TempDir, args: &[&str]) -> String {
+    let output = task_cmd_env(dir)
+        .arg("create")
+        .args(args)
+        .output()
+        .expect("create failed");
+    created_id(&String::from_utf8_lossy(&output.stdout)).to_string()
+}
+
+fn stdout_json(cmd: &mut assert_cmd::Command) -> serde_json::Value {
+    let output = cmd.output().expect("command failed");
+    assert!(output.status.success(), "{output:?}");
+    serde_json::from_slice(&output.stdout).expect("stdout is not JSON")
+}
+
 // --- create ---
 
 #[test]
@@ -90,6 +107,59 @@ fn create_description_too_long_succeeds() {
         .stdout(predicate::str::contains("TASK_ADD_"));
 }
 
+#[test]
+fn create_with_ulid_id_format() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let hex_id = create_task(&dir, &["hex task"]);
+    assert_eq!(hex_id.len(), 8);
+    task_cmd_env(&dir)
+        .args(["config", "set", "id_format", "ulid"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("tasks.log.id_format"));
+    // the format is kept with the log, so every cwd gets it
+    let elsewhere = tempfile::tempdir().unwrap();
+    let output = task_cmd_env(&dir)
+        .current_dir(elsewhere.path())
+        .args(["create", "ulid task"])
+        .output()
+        .unwrap();
+    let id = created_id(&String::from_utf8_lossy(&output.stdout)).to_string();
+    assert_eq!(id.len(), 26);
+    task_cmd_env(&dir)
+        .args(["update", &id[..12].to_lowercase(), "doing"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(format!("TASK_DOING_{id}")));
+    task_cmd_env(&dir)
+        .args(["update", &hex_id, "doing"])
+        .assert()
+        .success();
+    task_cmd_env(&dir)
+        .args(["config", "list"])
+        .assert()
+        .stdout(predicate::str::contains("id_format = \"ulid\"  # log "));
+}
+
+#[test]
+fn config_set_rejects_unknown_id_format() {
+    let (mut cmd, _dir) = task_cmd_with_log();
+    cmd.args(["config", "set", "id_format", "uuid"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown ID format 'uuid'"));
+}
+
+#[test]
+fn config_set_id_format_rejects_global() {
+    let (mut cmd, dir) = task_cmd_with_log();
+    cmd.args(["config", "set", "id_format", "ulid", "--global"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("drop --global"));
+    assert!(!dir.path().join("tasks.log.id_format").exists());
+}
+
 // --- update ---
 
 #[test]
@@ -115,7 +185,7 @@ fn update_outputs_status_prefix() {
         .args(["update", id, "doing"])
         .assert()
         .success()
-        .stdout(predicate::str::starts_with(&format!("TASK_DOING_{id}")));
+        .stdout(predicate::str::starts_with(format!("TASK_DOING_{id}")));
 }
 
 #[test]
@@ -132,7 +202,7 @@ fn update_with_note() {
         .args(["update", id, "blocked", "API not ready"])
         .assert()
         .success()
-        .stdout(predicate::str::starts_with(&format!("TASK_BLOCKED_{id}")));
+        .stdout(predicate::str::starts_with(format!("TASK_BLOCKED_{id}")));
 }
 
 #[test]
@@ -150,50 +220,2084 @@ fn update_note_too_long_succeeds() {
         .args(["update", id, "blocked", &long_note])
         .assert()
         .success()
-        .stdout(predicate::str::starts_with(&format!("TASK_BLOCKED_{id}")));
+        .stdout(predicate::str::starts_with(format!("TASK_BLOCKED_{id}")));
 }
 
-// --- list ---
+#[test]
+fn concurrent_writers_produce_parseable_log() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let log_path = dir.path().join("tasks.log");
+    let id = create_task(&dir, &["shared"]);
+    let note = "x".repeat(64 * 1024);
+    let children: Vec<_> = (0..16)
+        .map(|i| {
+            let mut cmd = std::process::Command::new(env!("CARGO_BIN_EXE_task"));
+            cmd.env("TASK_LOG_PATH", &log_path);
+            if i % 2 == 0 {
+                cmd.args(["update", &id, "doing", &note]);
+            } else {
+                cmd.args(["create", "parallel", &note]);
+            }
+            cmd.stdout(std::process::Stdio::null())
+                .spawn()
+                .expect("spawn failed")
+        })
+        .collect();
+    for mut child in children {
+        assert!(child.wait().unwrap().success());
+    }
+    let raw = std::fs::read_to_string(&log_path).unwrap();
+    assert_eq!(raw.lines().count(), 17);
+    for line in raw.lines() {
+        serde_json::from_str::<serde_json::Value>(line).expect("interleaved line");
+    }
+}
 
 #[test]
-fn list_empty_succeeds() {
-    let (mut cmd, _dir) = task_cmd_with_log();
-    cmd.args(["list", "--all"]).assert().success();
+fn lock_timeout_reports_clear_error() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let log_path = dir.path().join("tasks.log");
+    let file = std::fs::File::create(&log_path).unwrap();
+    file.lock().unwrap();
+    task_cmd_env(&dir)
+        .env("TASK_LOCK_TIMEOUT", "0.1")
+        .args(["create", "blocked by lock"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("timed out"));
 }
 
-// --- get ---
+#[test]
+fn update_accepts_id_prefix() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["prefix"]);
+    task_cmd_env(&dir)
+        .args(["update", &id[..4], "doing"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(format!("TASK_DOING_{id}")));
+}
 
 #[test]
-fn get_nonexistent_id_fails() {
-    let (mut cmd, _dir) = task_cmd_with_log();
-    cmd.args(["get", "nonexist"])
+fn ambiguous_prefix_lists_candidates() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let log_path = dir.path().join("tasks.log");
+    let line = |id: &str, title: &str| {
+        format!(
+            "{{\"ts\":\"2026-02-22T14:30:00+09:00\",\"id\":\"{id}\",\"project\":\"p\",\"status\":\"todo\",\"title\":\"{title}\",\"description\":\"\",\"note\":\"\"}}\n"
+        )
+    };
+    std::fs::write(
+        &log_path,
+        line("a3f8c2d1", "Auth") + &line("a3000000", "Bug"),
+    )
+    .unwrap();
+    task_cmd_env(&dir)
+        .args(["get", "a3"])
         .assert()
         .failure()
-        .stderr(predicate::str::contains("not found"));
+        .stderr(predicate::str::contains("ambiguous prefix 'a3'"))
+        .stderr(predicate::str::contains("a3f8c2d1  Auth"))
+        .stderr(predicate::str::contains("a3000000  Bug"));
+    task_cmd_env(&dir)
+        .args(["get", "a3f"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a3f8c2d1 | p | Auth"));
 }
 
+// --- edit ---
+
 #[test]
-fn get_shows_history() {
-    let (mut cmd, dir) = task_cmd_with_log();
-    let create_output = cmd
-        .args(["create", "history test"])
-        .output()
-        .expect("create failed");
-    let stdout = String::from_utf8_lossy(&create_output.stdout);
-    let id = created_id(&stdout);
+fn edit_changes_fields_and_keeps_status() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["Fxi login"]);
+    task_cmd_env(&dir)
+        .args(["update", &id, "doing"])
+        .assert()
+        .success();
+    task_cmd_env(&dir)
+        .args(["edit", &id, "--title", "Fix login", "--project", "me/right"])
+        .assert()
+        .success()
+        .stdout(format!("TASK_EDITED_{id}\n"));
 
+    let value = stdout_json(task_cmd_env(&dir).args(["get", &id, "--json"]));
+    assert_eq!(value["task"]["title"], "Fix login");
+    assert_eq!(value["task"]["project"], "me/right");
+    assert_eq!(value["task"]["status"], "doing");
+    assert_eq!(value["history"].as_array().unwrap().len(), 3);
+    assert_eq!(value["history"][1]["title"], "Fxi login");
+
+    // the task left the current project
+    assert!(list_ids(&dir, &[]).is_empty());
     task_cmd_env(&dir)
-        .args(["update", id, "doing"])
+        .args(["get", &id])
+        .assert()
+        .stdout(predicate::str::contains("title: Fxi login -> Fix login"))
+        .stdout(predicate::str::contains("project: "))
+        .stdout(predicate::str::contains("-> me/right"));
+}
+
+#[test]
+fn update_keeps_project_moved_by_edit() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["misfiled"]);
+    task_cmd_env(&dir)
+        .args(["edit", &id, "--project", "me/right"])
         .assert()
         .success();
+    let value = stdout_json(task_cmd_env(&dir).args(["update", &id, "doing", "--json"]));
+    assert_eq!(value["project"], "me/right");
+    assert!(list_ids(&dir, &[]).is_empty());
+}
 
+#[test]
+fn edit_requires_a_field() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["t"]);
     task_cmd_env(&dir)
-        .args(["get", id])
+        .args(["edit", &id])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("nothing to edit"));
+    task_cmd_env(&dir)
+        .args(["edit", &id, "--title", " "])
+        .assert()
+        .failure();
+    task_cmd_env(&dir)
+        .args(["edit", &id, "--parent", &id])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn edit_rejects_parent_cycle() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let epic = create_task(&dir, &["epic"]);
+    let child = create_task(&dir, &["child", "--parent", &epic]);
+    let grandchild = create_task(&dir, &["grandchild", "--parent", &child]);
+    task_cmd_env(&dir)
+        .args(["edit", &epic, "--parent", &grandchild])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!(
+            "parent cycle: {epic} -> {grandchild} -> {child} -> {epic}"
+        )));
+    task_cmd_env(&dir)
+        .args(["edit", &grandchild, "--parent", &epic])
+        .assert()
+        .success();
+    assert_eq!(list_ids(&dir, &["--tree"]).len(), 3);
+}
+
+// --- delete / undo ---
+
+#[test]
+fn delete_hides_task_until_undone() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let keep = create_task(&dir, &["keep"]);
+    let junk1 = create_task(&dir, &["junk one"]);
+    let junk2 = create_task(&dir, &["junk two"]);
+    task_cmd_env(&dir)
+        .args(["delete", &junk1, &junk2])
         .assert()
         .success()
-        .stdout(predicate::str::contains("history test"))
-        .stdout(predicate::str::contains("todo"))
-        .stdout(predicate::str::contains("doing"));
+        .stdout(format!("TASK_DELETED_{junk1}\nTASK_DELETED_{junk2}\n"));
+    assert_eq!(
+        list_ids(&dir, &["--include-done", "--include-archived"]),
+        vec![keep]
+    );
+    task_cmd_env(&dir)
+        .args(["search", "junk"])
+        .assert()
+        .stdout("");
+    task_cmd_env(&dir)
+        .args(["update", &junk1, "doing"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("was deleted"));
+
+    // history stays visible, and undo brings the task back
+    task_cmd_env(&dir)
+        .args(["get", &junk1])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("deleted"));
+    task_cmd_env(&dir)
+        .args(["undo", &junk1])
+        .assert()
+        .success()
+        .stdout(format!("TASK_UNDONE_{junk1}\n"));
+    assert!(list_ids(&dir, &[]).contains(&junk1));
+    let value = stdout_json(task_cmd_env(&dir).args(["get", &junk1, "--json"]));
+    assert_eq!(value["history"].as_array().unwrap().len(), 3);
+    assert_eq!(value["task"]["kind"], "undo");
+}
+
+#[test]
+fn delete_refuses_tasks_others_refer_to() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let dep = create_task(&dir, &["groundwork"]);
+    let dependent = create_task(&dir, &["build on it", "--depends-on", &dep]);
+    let epic = create_task(&dir, &["epic"]);
+    let sub = create_task(&dir, &["part", "--parent", &epic]);
+
+    task_cmd_env(&dir)
+        .args(["delete", &dep])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!(
+            "{dependent}  build on it (depends on {dep})"
+        )));
+    task_cmd_env(&dir)
+        .args(["delete", &epic])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!(
+            "{sub}  part (sub-task of {epic})"
+        )))
+        .stderr(predicate::str::contains("--force"));
+    assert_eq!(list_ids(&dir, &[]).len(), 4);
+
+    // deleting the referring tasks along with them is fine
+    task_cmd_env(&dir)
+        .args(["delete", &epic, &sub])
+        .assert()
+        .success();
+    task_cmd_env(&dir)
+        .args(["delete", &dep, "--force"])
+        .assert()
+        .success();
+    assert_eq!(list_ids(&dir, &[]), vec![dependent]);
+}
+
+#[test]
+fn undo_restores_previous_states_one_by_one() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["t", "first"]);
+    task_cmd_env(&dir)
+        .args(["update", &id, "doing", "started"])
+        .assert()
+        .success();
+    task_cmd_env(&dir)
+        .args(["update", &id, "blocked", "oops", "--description", "second"])
+        .assert()
+        .success();
+
+    task_cmd_env(&dir).args(["undo", &id]).assert().success();
+    let value = stdout_json(task_cmd_env(&dir).args(["get", &id, "--json"]));
+    assert_eq!(value["task"]["status"], "doing");
+    assert_eq!(value["task"]["description"], "first");
+    assert_eq!(value["task"]["note"], "started");
+
+    task_cmd_env(&dir).args(["undo", &id]).assert().success();
+    let value = stdout_json(task_cmd_env(&dir).args(["get", &id, "--json"]));
+    assert_eq!(value["task"]["status"], "todo");
+    task_cmd_env(&dir)
+        .args(["get", &id])
+        .assert()
+        .stdout(predicate::str::contains("undo of doing"));
+
+    task_cmd_env(&dir)
+        .args(["undo", &id])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("nothing to undo"));
+}
+
+// --- schema ---
+
+#[test]
+fn entries_record_their_kind() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["t"]);
+    task_cmd_env(&dir)
+        .args(["update", &id, "doing"])
+        .assert()
+        .success();
+    task_cmd_env(&dir)
+        .args(["edit", &id, "--title", "T"])
+        .assert()
+        .success();
+    let value = stdout_json(task_cmd_env(&dir).args(["get", &id, "--json"]));
+    let kinds: Vec<&str> = value["history"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["kind"].as_str().unwrap())
+        .collect();
+    assert_eq!(kinds, vec!["create", "transition", "edit"]);
+    assert!(
+        value["history"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|e| e["v"] == 2)
+    );
+}
+
+#[test]
+fn legacy_and_unknown_kind_lines() {
+    use std::io::Write as _;
+    let (_cmd, dir) = task_cmd_with_log();
+    write_log_line(&dir, "01d00001", "todo", "2026-01-01T00:00:00+00:00");
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(dir.path().join("tasks.log"))
+        .unwrap();
+    writeln!(
+        file,
+        r#"{{"v":9,"kind":"reaction","ts":"2026-01-02T00:00:00+00:00","id":"01d00001","emoji":"+1"}}"#
+    )
+    .unwrap();
+    drop(file);
+
+    let value = stdout_json(task_cmd_env(&dir).args(["get", "01d00001", "--json"]));
+    assert_eq!(value["history"].as_array().unwrap().len(), 1);
+    assert_eq!(value["task"]["v"], 1);
+    task_cmd_env(&dir)
+        .args(["list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("01d00001"))
+        .stderr(predicate::str::contains("unknown kind"));
+    task_cmd_env(&dir).args(["doctor"]).assert().success();
+}
+
+// --- comment ---
+
+#[test]
+fn comment_keeps_state_and_shows_in_history() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["t"]);
+    task_cmd_env(&dir)
+        .args(["update", &id, "blocked", "which API version?"])
+        .assert()
+        .success();
+    task_cmd_env(&dir)
+        .args(["comment", &id, "use v2"])
+        .assert()
+        .success()
+        .stdout(format!("TASK_COMMENTED_{id}\n"));
+    task_cmd_env(&dir)
+        .args(["comment", &id, "--stdin"])
+        .write_stdin("long answer\nsecond line\n")
+        .assert()
+        .success();
+
+    let value = stdout_json(task_cmd_env(&dir).args(["get", &id, "--json"]));
+    assert_eq!(value["task"]["status"], "blocked");
+    let history = value["history"].as_array().unwrap();
+    assert_eq!(history.len(), 4);
+    assert_eq!(history[2]["kind"], "comment");
+    assert_eq!(history[3]["note"], "long answer\nsecond line");
+    task_cmd_env(&dir)
+        .args(["get", &id])
+        .assert()
+        .stdout(predicate::str::contains("(comment)"))
+        .stdout(predicate::str::contains("use v2"));
+
+    // undo passes over comments to the last real change
+    task_cmd_env(&dir).args(["undo", &id]).assert().success();
+    let value = stdout_json(task_cmd_env(&dir).args(["get", &id, "--json"]));
+    assert_eq!(value["task"]["status"], "todo");
+}
+
+#[test]
+fn comment_requires_text() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["t"]);
+    task_cmd_env(&dir).args(["comment", &id]).assert().failure();
+    task_cmd_env(&dir)
+        .args(["comment", &id, "--stdin"])
+        .write_stdin("  \n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("must not be empty"));
+}
+
+// --- compact ---
+
+#[test]
+fn compact_moves_old_finished_tasks_into_segments() {
+    let (_cmd, dir) = task_cmd_with_log();
+    write_log_line(&dir, "0ld00001", "todo", "2026-01-10T00:00:00+00:00");
+    write_log_line(&dir, "0ld00001", "done", "2026-01-12T00:00:00+00:00");
+    write_log_line(&dir, "0ld00002", "done", "2026-02-03T00:00:00+00:00");
+    write_log_line(&dir, "0ld00003", "todo", "2026-01-01T00:00:00+00:00");
+    let recent = create_task(&dir, &["recent", "--status", "done"]);
+
+    task_cmd_env(&dir)
+        .args(["compact", "--dry-run"])
+        .assert()
+        .success()
+        .stdout("would compact 2 task(s), 3 entries into tasks.2026-01.log, tasks.2026-02.log\n");
+    assert!(!dir.path().join("tasks.2026-01.log").exists());
+    task_cmd_env(&dir)
+        .args(["compact"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("compacted 2 task(s)"));
+    let live = std::fs::read_to_string(dir.path().join("tasks.log")).unwrap();
+    assert_eq!(live.lines().count(), 2);
+    let segment = std::fs::read_to_string(dir.path().join("tasks.2026-01.log")).unwrap();
+    assert_eq!(segment.lines().count(), 2);
+    let index = std::fs::read_to_string(dir.path().join("tasks.log.compacted")).unwrap();
+    assert_eq!(index, r#"["0ld00001","0ld00002"]"#);
+
+    assert_eq!(
+        list_ids(&dir, &["--include-done"]),
+        vec!["0ld00003", recent.as_str()]
+    );
+    // get and search still see the compacted history
+    let value = stdout_json(task_cmd_env(&dir).args(["get", "0ld00001", "--json"]));
+    assert_eq!(value["history"].as_array().unwrap().len(), 2);
+    task_cmd_env(&dir)
+        .args(["get", "0ld00002"])
+        .assert()
+        .success();
+    task_cmd_env(&dir)
+        .args(["search", "task 0ld00002"])
+        .assert()
+        .stdout(predicate::str::contains("0ld00002"));
+
+    task_cmd_env(&dir)
+        .args(["compact"])
+        .assert()
+        .stdout("nothing to compact\n");
+    task_cmd_env(&dir)
+        .args(["compact", "--older-than", "1000000000d"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("out of range"));
+}
+
+#[test]
+fn compact_keeps_tasks_that_open_tasks_depend_on() {
+    let (_cmd, dir) = task_cmd_with_log();
+    write_log_line(&dir, "0ld00001", "done", "2026-01-12T00:00:00+00:00");
+    let open = create_task(&dir, &["open", "--depends-on", "0ld00001"]);
+    task_cmd_env(&dir)
+        .args(["compact"])
+        .assert()
+        .stdout("nothing to compact\n");
+    assert_eq!(list_ids(&dir, &["--ready"]), vec![open]);
+}
+
+#[test]
+fn compact_keeps_subtasks_of_open_parents() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let old = "2026-01-12T00:00:00+00:00";
+    write_log_line(&dir, "epic0001", "todo", old);
+    for (id, status) in [
+        ("sub00001", "done"),
+        ("sub00002", "done"),
+        ("sub00003", "todo"),
+    ] {
+        write_log_line_with(
+            &dir,
+            id,
+            status,
+            old,
+            serde_json::json!({"parent": "epic0001"}),
+        );
+    }
+    // a finished epic with finished sub-tasks moves as a whole
+    write_log_line(&dir, "epic0002", "done", old);
+    write_log_line_with(
+        &dir,
+        "sub00004",
+        "done",
+        old,
+        serde_json::json!({"parent": "epic0002"}),
+    );
+
+    task_cmd_env(&dir)
+        .args(["compact"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("compacted 2 task(s)"));
+    task_cmd_env(&dir)
+        .args(["list"])
+        .assert()
+        .stdout(predicate::str::contains("task epic0001 (2/3)"));
+    assert_eq!(
+        list_ids(&dir, &["--include-done"]),
+        vec!["epic0001", "sub00001", "sub00002", "sub00003"]
+    );
+}
+
+#[test]
+fn compact_while_agents_append_loses_nothing() {
+    let (_cmd, dir) = task_cmd_with_log();
+    for i in 0..20 {
+        write_log_line(
+            &dir,
+            &format!("0ld{i:05}"),
+            "done",
+            "2026-01-12T00:00:00+00:00",
+        );
+    }
+    let log_path = dir.path().join("tasks.log");
+    let spawn = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_task"))
+            .env("TASK_LOG_PATH", &log_path)
+            .args(args)
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .expect("spawn failed")
+    };
+    let mut children: Vec<_> = (0..15)
+        .map(|i| spawn(&["create", &format!("new {i}")]))
+        .collect();
+    children.push(spawn(&["compact"]));
+    children.extend((15..30).map(|i| spawn(&["create", &format!("new {i}")])));
+    for mut child in children {
+        assert!(child.wait().unwrap().success());
+    }
+    let titles = task_cmd_env(&dir)
+        .args(["list", "--all", "--json"])
+        .output()
+        .unwrap();
+    let tasks: serde_json::Value = serde_json::from_slice(&titles.stdout).unwrap();
+    assert_eq!(tasks.as_array().unwrap().len(), 30);
+    let segment = std::fs::read_to_string(dir.path().join("tasks.2026-01.log")).unwrap();
+    assert_eq!(segment.lines().count(), 20);
+}
+
+// --- list ---
+
+#[test]
+fn list_empty_succeeds() {
+    let (mut cmd, _dir) = task_cmd_with_log();
+    cmd.args(["list", "--all"]).assert().success();
+}
+
+#[test]
+fn list_filters_by_tags() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let both = create_task(&dir, &["both", "--tag", "backend", "--tag", "urgent"]);
+    let backend = create_task(&dir, &["backend only", "--tag", "backend"]);
+    let untagged = create_task(&dir, &["untagged"]);
+    task_cmd_env(&dir)
+        .args(["list", "--tag", "backend"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(&both))
+        .stdout(predicate::str::contains(&backend))
+        .stdout(predicate::str::contains(&untagged).not());
+    task_cmd_env(&dir)
+        .args(["list", "--tag", "backend", "--tag", "urgent"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(&both))
+        .stdout(predicate::str::contains(&backend).not());
+}
+
+#[test]
+fn update_edits_tags_and_carries_them_forward() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["tagged", "--tag", "bug"]);
+    task_cmd_env(&dir)
+        .args(["update", &id, "doing", "--add-tag", "agent:codex"])
+        .assert()
+        .success();
+    task_cmd_env(&dir)
+        .args(["update", &id, "blocked", "--remove-tag", "bug"])
+        .assert()
+        .success();
+    let json = stdout_json(task_cmd_env(&dir).args(["get", &id, "--json"]));
+    assert_eq!(json["task"]["tags"], serde_json::json!(["agent:codex"]));
+    assert_eq!(
+        json["history"][1]["tags"],
+        serde_json::json!(["bug", "agent:codex"])
+    );
+    task_cmd_env(&dir)
+        .args(["get", &id])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("tags: agent:codex"));
+}
+
+#[test]
+fn list_sorts_by_priority_then_age() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let old_unset = create_task(&dir, &["old unset"]);
+    let low = create_task(&dir, &["low", "--priority", "p3"]);
+    let urgent = create_task(&dir, &["urgent", "--priority", "p0"]);
+    let low_newer = create_task(&dir, &["low newer", "--priority", "3"]);
+    let json = stdout_json(task_cmd_env(&dir).args(["list", "--json"]));
+    let ids: Vec<&str> = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, vec![&urgent, &low, &low_newer, &old_unset]);
+    assert_eq!(json[0]["priority"], 0);
+
+    task_cmd_env(&dir)
+        .args(["update", &old_unset, "todo", "--priority", "p1"])
+        .assert()
+        .success();
+    let output = task_cmd_env(&dir).arg("list").output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    assert!(lines.next().unwrap().contains("PRI"));
+    assert!(lines.next().unwrap().starts_with(&urgent));
+    assert!(lines.next().unwrap().starts_with(&old_unset));
+}
+
+#[test]
+fn invalid_priority_rejected() {
+    let (mut cmd, _dir) = task_cmd_with_log();
+    cmd.args(["create", "x", "--priority", "urgent"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid priority"));
+}
+
+// --- list time filters / sorting ---
+
+fn list_ids(dir: &tempfile::TempDir, args: &[&str]) -> Vec<String> {
+    let value = stdout_json(task_cmd_env(dir).arg("list").args(args).arg("--json"));
+    value
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["id"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn list_filters_by_creation_and_update_time() {
+    let (_cmd, dir) = task_cmd_with_log();
+    write_log_line(&dir, "0ld00001", "todo", "2026-01-01T00:00:00+00:00");
+    write_log_line(&dir, "0ld00002", "todo", "2026-01-10T00:00:00+00:00");
+    write_log_line(&dir, "0ld00001", "doing", "2026-01-20T00:00:00+00:00");
+    let fresh = create_task(&dir, &["fresh"]);
+
+    assert_eq!(list_ids(&dir, &["--since", "2026-01-05"]).len(), 2);
+    assert_eq!(
+        list_ids(&dir, &["--since", "2026-01-05", "--until", "2026-02-01"]),
+        vec!["0ld00002"]
+    );
+    assert_eq!(
+        list_ids(&dir, &["--until", "2026-01-05T00:00:00+00:00"]),
+        vec!["0ld00001"]
+    );
+    assert_eq!(list_ids(&dir, &["--updated-within", "24h"]), vec![fresh]);
+    task_cmd_env(&dir)
+        .args(["list", "--since", "yesterday"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid time 'yesterday'"));
+}
+
+#[test]
+fn list_rejects_time_filters_out_of_range() {
+    let (_cmd, dir) = task_cmd_with_log();
+    create_task(&dir, &["t"]);
+    for args in [
+        ["--since", "5000000000d"],
+        ["--updated-within", "1000000000d"],
+    ] {
+        task_cmd_env(&dir)
+            .arg("list")
+            .args(args)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("out of range"));
+    }
+}
+
+#[test]
+fn list_accepts_multiple_and_negated_statuses() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let todo = create_task(&dir, &["a"]);
+    let doing = create_task(&dir, &["b", "--status", "doing"]);
+    let done = create_task(&dir, &["c", "--status", "done"]);
+    assert_eq!(
+        list_ids(&dir, &["--status", "todo", "--status", "doing"]),
+        vec![todo.clone(), doing.clone()]
+    );
+    assert_eq!(
+        list_ids(&dir, &["--not-status", "done"]),
+        vec![todo.clone(), doing]
+    );
+    assert_eq!(list_ids(&dir, &["done"]), vec![done]);
+    assert_eq!(
+        list_ids(&dir, &["todo", "--status", "done", "--not-status", "done"]),
+        vec![todo]
+    );
+}
+
+#[test]
+fn list_sorts_by_key() {
+    let (_cmd, dir) = task_cmd_with_log();
+    write_log_line(&dir, "0ld00001", "todo", "2026-01-01T00:00:00+00:00");
+    write_log_line(&dir, "0ld00002", "blocked", "2026-01-10T00:00:00+00:00");
+    write_log_line(&dir, "0ld00001", "doing", "2026-01-20T00:00:00+00:00");
+    let urgent = create_task(&dir, &["urgent", "--priority", "p0"]);
+    assert_eq!(list_ids(&dir, &[])[0], urgent);
+    assert_eq!(
+        list_ids(&dir, &["--sort", "created"]),
+        vec!["0ld00001", "0ld00002", urgent.as_str()]
+    );
+    assert_eq!(
+        list_ids(&dir, &["--sort", "updated"]),
+        vec![urgent.as_str(), "0ld00001", "0ld00002"]
+    );
+    assert_eq!(
+        list_ids(&dir, &["--sort", "status"]),
+        vec!["0ld00002", "0ld00001", urgent.as_str()]
+    );
+}
+
+// --- done / archive ---
+
+#[test]
+fn list_hides_terminal_statuses_by_default() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let open = create_task(&dir, &["open"]);
+    let done = create_task(&dir, &["finished", "--status", "done"]);
+    assert_eq!(list_ids(&dir, &[]), vec![open.clone()]);
+    assert_eq!(
+        list_ids(&dir, &["--include-done"]),
+        vec![open.clone(), done.clone()]
+    );
+    assert_eq!(list_ids(&dir, &["--status", "done"]), vec![done.clone()]);
+
+    std::fs::create_dir_all(dir.path().join("config/task")).unwrap();
+    std::fs::write(
+        dir.path().join("config/task/config.toml"),
+        "terminal_statuses = [\"done\", \"todo\"]\n",
+    )
+    .unwrap();
+    assert!(list_ids(&dir, &[]).is_empty());
+}
+
+#[test]
+fn archive_hides_tasks_but_keeps_history() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let a = create_task(&dir, &["a"]);
+    let b = create_task(&dir, &["b"]);
+    task_cmd_env(&dir)
+        .args(["archive", &a])
+        .assert()
+        .success()
+        .stdout(format!("TASK_ARCHIVED_{a}\n"));
+    assert_eq!(list_ids(&dir, &[]), vec![b.clone()]);
+    assert_eq!(
+        list_ids(&dir, &["--include-archived"]),
+        vec![a.clone(), b.clone()]
+    );
+    task_cmd_env(&dir)
+        .args(["search", "a", "--json"])
+        .assert()
+        .stdout(predicate::str::contains(&a).not());
+    let value = stdout_json(task_cmd_env(&dir).args(["get", &a, "--json"]));
+    assert_eq!(value["task"]["archived"], true);
+    assert_eq!(value["history"].as_array().unwrap().len(), 2);
+
+    // archiving twice is a no-op; updating brings the task back
+    task_cmd_env(&dir).args(["archive", &a]).assert().stdout("");
+    // editing or commenting keeps it archived
+    task_cmd_env(&dir)
+        .args(["edit", &a, "--title", "a2"])
+        .assert()
+        .success();
+    task_cmd_env(&dir)
+        .args(["comment", &a, "still archived"])
+        .assert()
+        .success();
+    let value = stdout_json(task_cmd_env(&dir).args(["get", &a, "--json"]));
+    assert_eq!(value["task"]["title"], "a2");
+    assert_eq!(value["task"]["archived"], true);
+    assert_eq!(list_ids(&dir, &[]), vec![b.clone()]);
+    task_cmd_env(&dir)
+        .args(["update", &a, "doing"])
+        .assert()
+        .success();
+    assert!(list_ids(&dir, &[]).contains(&a));
+}
+
+#[test]
+fn archive_done_before_only_touches_old_terminal_tasks() {
+    let (_cmd, dir) = task_cmd_with_log();
+    write_log_line(&dir, "0ld00001", "done", "2026-01-01T00:00:00+00:00");
+    write_log_line(&dir, "0ld00002", "todo", "2026-01-01T00:00:00+00:00");
+    let recent = create_task(&dir, &["recent", "--status", "done"]);
+    task_cmd_env(&dir)
+        .args(["archive", "--done-before", "30d"])
+        .assert()
+        .success()
+        .stdout("TASK_ARCHIVED_0ld00001\n");
+    assert_eq!(
+        list_ids(&dir, &["--include-done"]),
+        vec!["0ld00002", recent.as_str()]
+    );
+    task_cmd_env(&dir)
+        .args(["archive", "0ld00002", "--done-before", "30d"])
+        .assert()
+        .failure();
+    task_cmd_env(&dir)
+        .args(["archive", "--done-before", "1000000000d"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("out of range"));
+}
+
+// --- dependencies ---
+
+#[test]
+fn list_ready_waits_for_dependencies() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let first = create_task(&dir, &["first step"]);
+    let second = create_task(&dir, &["second step", "--depends-on", &first]);
+    let ready_ids = |dir: &tempfile::TempDir| -> Vec<String> {
+        let json = stdout_json(task_cmd_env(dir).args(["list", "--ready", "--json"]));
+        json.as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["id"].as_str().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(ready_ids(&dir), vec![first.clone()]);
+    task_cmd_env(&dir)
+        .args(["update", &first, "done"])
+        .assert()
+        .success();
+    assert_eq!(ready_ids(&dir), vec![second]);
+}
+
+#[test]
+fn depends_on_unknown_task_fails() {
+    let (mut cmd, _dir) = task_cmd_with_log();
+    cmd.args(["create", "x", "--depends-on", "deadbeef"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not found"));
+}
+
+#[test]
+fn update_rejects_dependency_cycle() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let a = create_task(&dir, &["a"]);
+    let b = create_task(&dir, &["b", "--depends-on", &a]);
+    let c = create_task(&dir, &["c", "--depends-on", &b]);
+    task_cmd_env(&dir)
+        .args(["update", &a, "todo", "--depends-on", &c])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!(
+            "dependency cycle: {a} -> {c} -> {b} -> {a}"
+        )));
+    task_cmd_env(&dir)
+        .args(["update", &a, "todo", "--depends-on", &a])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("dependency cycle"));
+}
+
+#[test]
+fn graph_shows_upstream_and_downstream() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let a = create_task(&dir, &["schema"]);
+    let b = create_task(&dir, &["migration", "--depends-on", &a]);
+    let c = create_task(&dir, &["deploy", "--depends-on", &b]);
+    task_cmd_env(&dir)
+        .args(["graph", &b])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("upstream (depends on):"))
+        .stdout(predicate::str::contains(format!("  {a}")))
+        .stdout(predicate::str::contains(format!("  {c}")));
+    let json = stdout_json(task_cmd_env(&dir).args(["graph", &c, "--json"]));
+    assert_eq!(json["upstream"][0]["id"], b.as_str());
+    assert_eq!(json["upstream"][0]["children"][0]["id"], a.as_str());
+    assert_eq!(json["downstream"], serde_json::json!([]));
+}
+
+// --- sub-tasks ---
+
+#[test]
+fn subtasks_roll_up_on_parent_row() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let epic = create_task(&dir, &["epic"]);
+    let a = create_task(&dir, &["part a", "--parent", &epic[..6]]);
+    let b = create_task(&dir, &["part b", "--parent", &epic]);
+    create_task(&dir, &["unrelated"]);
+    task_cmd_env(&dir)
+        .args(["update", &a, "done"])
+        .assert()
+        .success();
+    task_cmd_env(&dir)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("epic (1/2)"));
+
+    let output = task_cmd_env(&dir)
+        .args(["list", "--tree", "--include-done"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let rows: Vec<&str> = stdout.lines().skip(1).collect();
+    assert!(rows[0].starts_with(&epic));
+    assert!(rows[1].starts_with(&a) && rows[1].ends_with("  part a"));
+    assert!(rows[2].starts_with(&b) && rows[2].ends_with("  part b"));
+    assert!(rows[3].ends_with(" unrelated"));
+
+    task_cmd_env(&dir)
+        .args(["get", &epic])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("subtasks:"))
+        .stdout(predicate::str::contains(&a))
+        .stdout(predicate::str::contains(&b));
+    task_cmd_env(&dir)
+        .args(["get", &b])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("parent: {epic}")));
+    let json = stdout_json(task_cmd_env(&dir).args(["get", &epic, "--json"]));
+    assert_eq!(json["subtasks"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn create_with_unknown_parent_fails() {
+    let (mut cmd, _dir) = task_cmd_with_log();
+    cmd.args(["create", "orphan", "--parent", "deadbeef"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not found"));
+}
+
+// --- claim ---
+
+#[test]
+fn claim_blocks_other_agents_until_lease_expires() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["contested"]);
+    task_cmd_env(&dir)
+        .args(["claim", &id, "--agent", "claude"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("TASK_DOING_{id}")));
+    task_cmd_env(&dir)
+        .args(["claim", &id, "--agent", "codex"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is claimed by claude"));
+    // Same agent renews; a zero TTL makes the lease lapse immediately.
+    task_cmd_env(&dir)
+        .args(["claim", &id, "--agent", "claude", "--ttl", "0s"])
+        .assert()
+        .success();
+    task_cmd_env(&dir)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("claude (expired)"));
+    task_cmd_env(&dir)
+        .args(["claim", &id, "--agent", "codex"])
+        .assert()
+        .success();
+    task_cmd_env(&dir)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("HOLDER"))
+        .stdout(predicate::str::contains("codex "));
+    let json = stdout_json(task_cmd_env(&dir).args(["get", &id, "--json"]));
+    assert_eq!(json["task"]["claimed_by"], "codex");
+    assert_eq!(json["task"]["note"], "claimed by codex");
+}
+
+#[test]
+fn claim_refuses_finished_and_waiting_tasks_without_force() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let done = create_task(&dir, &["shipped", "--status", "done"]);
+    let waiting = create_task(&dir, &["later", "--depends-on", &done]);
+    let blocker = create_task(&dir, &["first"]);
+    task_cmd_env(&dir)
+        .args(["update", &waiting, "todo", "--depends-on", &blocker])
+        .assert()
+        .success();
+
+    task_cmd_env(&dir)
+        .args(["claim", &done])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!("task '{done}' is done")));
+    task_cmd_env(&dir)
+        .args(["claim", &waiting])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!(
+            "is waiting on {blocker} (todo)"
+        )));
+    let value = stdout_json(task_cmd_env(&dir).args(["get", &done, "--json"]));
+    assert_eq!(value["task"]["status"], "done");
+
+    task_cmd_env(&dir)
+        .args(["claim", &waiting, "--force"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("TASK_DOING_{waiting}")));
+}
+
+#[test]
+fn claim_rejects_out_of_range_ttl() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["t"]);
+    task_cmd_env(&dir)
+        .args(["claim", &id, "--ttl", "9999999999w"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--ttl is out of range"));
+    task_cmd_env(&dir)
+        .args(["claim", &id, "--ttl", "99999999999999999d"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("out of range"));
+}
+
+#[test]
+fn claim_next_picks_top_ready_task() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let blocker = create_task(&dir, &["blocker", "--priority", "p2"]);
+    create_task(
+        &dir,
+        &["waits", "--priority", "p0", "--depends-on", &blocker],
+    );
+    let low = create_task(&dir, &["low"]);
+    let json = stdout_json(task_cmd_env(&dir).args(["claim", "--next", "--agent", "a", "--json"]));
+    assert_eq!(json["id"], blocker.as_str());
+    assert_eq!(json["status"], "doing");
+    let json = stdout_json(task_cmd_env(&dir).args(["claim", "--next", "--agent", "b", "--json"]));
+    assert_eq!(json["id"], low.as_str());
+    task_cmd_env(&dir)
+        .args(["claim", "--next", "--agent", "c"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no ready task to claim"));
+}
+
+#[test]
+fn update_away_from_doing_releases_claim() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["finish me"]);
+    task_cmd_env(&dir)
+        .args(["claim", &id, "--agent", "claude"])
+        .assert()
+        .success();
+    task_cmd_env(&dir)
+        .args(["update", &id, "inreview"])
+        .assert()
+        .success();
+    let json = stdout_json(task_cmd_env(&dir).args(["get", &id, "--json"]));
+    assert!(json["task"]["claimed_by"].is_null());
+}
+
+#[test]
+fn concurrent_claim_next_has_single_winner() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let log_path = dir.path().join("tasks.log");
+    create_task(&dir, &["only one"]);
+    let children: Vec<_> = (0..8)
+        .map(|i| {
+            std::process::Command::new(env!("CARGO_BIN_EXE_task"))
+                .env("TASK_LOG_PATH", &log_path)
+                .args(["claim", "--next", "--agent", &format!("agent{i}")])
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .spawn()
+                .expect("spawn failed")
+        })
+        .collect();
+    let winners = children
+        .into_iter()
+        .filter_map(|mut c| c.wait().unwrap().success().then_some(()))
+        .count();
+    assert_eq!(winners, 1);
+}
+
+// --- heartbeat / stale ---
+
+fn write_log_line(dir: &tempfile::TempDir, id: &str, status: &str, ts: &str) {
+    write_log_line_with(dir, id, status, ts, serde_json::json!({}));
+}
+
+/// Like `write_log_line`, with `extra` fields merged into the entry.
+fn write_log_line_with(
+    dir: &tempfile::TempDir,
+    id: &str,
+    status: &str,
+    ts: &str,
+    extra: serde_json::Value,
+) {
+    use std::io::Write as _;
+    let output = task_cmd_env(dir)
+        .args(["project", "show"])
+        .output()
+        .unwrap();
+    let project = String::from_utf8_lossy(&output.stdout);
+    let mut line = serde_json::json!({
+        "ts": ts, "id": id, "project": project.trim(),
+        "status": status, "title": format!("task {id}"), "description": "", "note": "",
+    });
+    line.as_object_mut()
+        .unwrap()
+        .extend(extra.as_object().unwrap().clone());
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.path().join("tasks.log"))
+        .unwrap();
+    writeln!(file, "{line}").unwrap();
+}
+
+#[test]
+fn stale_lists_idle_doing_tasks() {
+    let (_cmd, dir) = task_cmd_with_log();
+    write_log_line(&dir, "0ld00001", "doing", "2026-01-01T00:00:00+00:00");
+    write_log_line(&dir, "0ld00002", "todo", "2026-01-01T00:00:00+00:00");
+    let fresh = create_task(&dir, &["fresh", "--status", "doing"]);
+    task_cmd_env(&dir)
+        .args(["stale", "--all"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("0ld00001"))
+        .stdout(predicate::str::contains("0ld00002").not())
+        .stdout(predicate::str::contains(&fresh).not());
+    let json =
+        stdout_json(task_cmd_env(&dir).args(["stale", "--all", "--older-than", "0s", "--json"]));
+    assert_eq!(json.as_array().unwrap().len(), 2);
+    assert_eq!(json[0]["task"]["id"], "0ld00001");
+}
+
+#[test]
+fn heartbeat_keeps_task_fresh() {
+    let (_cmd, dir) = task_cmd_with_log();
+    write_log_line(&dir, "0ld00001", "doing", "2026-01-01T00:00:00+00:00");
+    task_cmd_env(&dir)
+        .args(["heartbeat", "0ld0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("heartbeat: 0ld00001"));
+    let value = stdout_json(task_cmd_env(&dir).args(["heartbeat", "0ld0", "--json"]));
+    assert_eq!(value["id"], "0ld00001");
+    assert!(value["ts"].as_str().is_some());
+    task_cmd_env(&dir)
+        .args(["heartbeat", "0ld0", "--format", "jsonl"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("{\"id\":\"0ld00001\",\"ts\":"));
+    task_cmd_env(&dir)
+        .args(["stale", "--all"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+    let raw = std::fs::read_to_string(dir.path().join("tasks.log")).unwrap();
+    assert_eq!(raw.lines().count(), 1);
+}
+
+#[test]
+fn stale_block_moves_tasks_to_blocked() {
+    let (_cmd, dir) = task_cmd_with_log();
+    write_log_line(&dir, "0ld00001", "doing", "2026-01-01T00:00:00+00:00");
+    task_cmd_env(&dir)
+        .args(["stale", "--all", "--block"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("TASK_BLOCKED_0ld00001"));
+    let json = stdout_json(task_cmd_env(&dir).args(["get", "0ld00001", "--json"]));
+    assert_eq!(json["task"]["status"], "blocked");
+    assert!(
+        json["task"]["note"]
+            .as_str()
+            .unwrap()
+            .starts_with("stale: no activity")
+    );
+    task_cmd_env(&dir)
+        .args(["stale", "--all"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+}
+
+// --- search ---
+
+#[test]
+fn search_finds_matches_across_history_once_per_task() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["Fix OAuth callback", "redirect URI is wrong"]);
+    create_task(&dir, &["Unrelated"]);
+    task_cmd_env(&dir)
+        .args(["update", &id, "blocked", "waiting for oauth app approval"])
+        .assert()
+        .success();
+    task_cmd_env(&dir)
+        .args(["update", &id, "doing"])
+        .assert()
+        .success();
+    let output = task_cmd_env(&dir)
+        .args(["search", "oauth"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "{stdout}");
+    assert!(lines[0].starts_with(&id) && lines[0].contains("doing"));
+    assert_eq!(lines[1], "  title: Fix OAuth callback");
+    assert!(lines[2].contains("blocked): waiting for oauth app approval"));
+    assert!(!stdout.contains('\x1b'));
+}
+
+#[test]
+fn search_options() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let a = create_task(&dir, &["OAuth login"]);
+    let b = create_task(&dir, &["oauth2 token refresh", "--status", "done"]);
+    let ids = |args: &[&str]| -> Vec<String> {
+        let value = stdout_json(task_cmd_env(&dir).arg("search").args(args).arg("--json"));
+        value
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["task"]["id"].as_str().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(ids(&["oauth"]), vec![a.clone(), b.clone()]);
+    assert_eq!(ids(&["OAuth", "--case-sensitive"]), vec![a.clone()]);
+    assert_eq!(ids(&["oauth\\d", "--regex"]), vec![b.clone()]);
+    assert_eq!(ids(&["oauth", "--status", "todo"]), vec![a.clone()]);
+    assert!(ids(&["oauth.login"]).is_empty());
+}
+
+#[test]
+fn search_highlights_with_color_always() {
+    let (_cmd, dir) = task_cmd_with_log();
+    create_task(&dir, &["Fix OAuth callback"]);
+    task_cmd_env(&dir)
+        .args(["search", "oauth", "--color", "always"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Fix \x1b[1;31mOAuth\x1b[0m callback",
+        ));
+}
+
+#[test]
+fn search_rejects_invalid_regex() {
+    let (mut cmd, _dir) = task_cmd_with_log();
+    cmd.args(["search", "(", "--regex"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid regex"));
+}
+
+// --- get ---
+
+#[test]
+fn get_nonexistent_id_fails() {
+    let (mut cmd, _dir) = task_cmd_with_log();
+    cmd.args(["get", "nonexist"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not found"));
+}
+
+#[test]
+fn get_shows_history() {
+    let (mut cmd, dir) = task_cmd_with_log();
+    let create_output = cmd
+        .args(["create", "history test"])
+        .output()
+        .expect("create failed");
+    let stdout = String::from_utf8_lossy(&create_output.stdout);
+    let id = created_id(&stdout);
+
+    task_cmd_env(&dir)
+        .args(["update", id, "doing"])
+        .assert()
+        .success();
+
+    task_cmd_env(&dir)
+        .args(["get", id])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("history test"))
+        .stdout(predicate::str::contains("todo"))
+        .stdout(predicate::str::contains("doing"));
+}
+
+// --- json output ---
+
+#[test]
+fn create_json_outputs_entry() {
+    let (mut cmd, _dir) = task_cmd_with_log();
+    let json = stdout_json(cmd.args(["create", "json task", "desc", "--json"]));
+    assert_eq!(json["title"], "json task");
+    assert_eq!(json["description"], "desc");
+    assert_eq!(json["status"], "todo");
+    assert_eq!(json["id"].as_str().unwrap().len(), 8);
+}
+
+#[test]
+fn update_json_outputs_entry() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["json update"]);
+    let json =
+        stdout_json(task_cmd_env(&dir).args(["--format", "json", "update", &id, "blocked", "why"]));
+    assert_eq!(json["id"], id.as_str());
+    assert_eq!(json["status"], "blocked");
+    assert_eq!(json["note"], "why");
+}
+
+#[test]
+fn list_json_is_array_of_entries() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(
+        &dir,
+        &[
+            "a status longer than eight",
+            "--status",
+            "waiting-for-review",
+        ],
+    );
+    let json = stdout_json(task_cmd_env(&dir).args(["list", "--json"]));
+    let tasks = json.as_array().unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0]["id"], id.as_str());
+    assert_eq!(tasks[0]["status"], "waiting-for-review");
+}
+
+#[test]
+fn list_json_empty_is_empty_array() {
+    let (mut cmd, _dir) = task_cmd_with_log();
+    let json = stdout_json(cmd.args(["list", "--all", "--json"]));
+    assert_eq!(json, serde_json::json!([]));
+}
+
+#[test]
+fn list_jsonl_one_entry_per_line() {
+    let (_cmd, dir) = task_cmd_with_log();
+    create_task(&dir, &["one"]);
+    create_task(&dir, &["two"]);
+    let output = task_cmd_env(&dir)
+        .args(["list", "--format", "jsonl"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2);
+    for line in lines {
+        let value: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert!(value["id"].is_string());
+    }
+}
+
+#[test]
+fn get_json_has_task_and_history() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["detail"]);
+    task_cmd_env(&dir)
+        .args(["update", &id, "doing"])
+        .assert()
+        .success();
+    let json = stdout_json(task_cmd_env(&dir).args(["get", &id, "--json"]));
+    assert_eq!(json["task"]["status"], "doing");
+    let history = json["history"].as_array().unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0]["status"], "todo");
+}
+
+#[test]
+fn json_and_format_conflict() {
+    let (mut cmd, _dir) = task_cmd_with_log();
+    cmd.args(["list", "--json", "--format", "table"])
+        .assert()
+        .failure();
+}
+
+// --- workflow ---
+
+const WORKFLOW: &str = r#"
+[workflow]
+statuses = ["todo", "doing", "blocked", "inreview", "done"]
+
+[workflow.aliases]
+wip = "doing"
+
+[workflow.transitions]
+todo = ["doing", "done"]
+doing = ["blocked", "inreview", "done"]
+"#;
+
+fn workflow_dir() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join(".task.toml"), WORKFLOW).unwrap();
+    dir
+}
+
+fn workflow_task(dir: &tempfile::TempDir) -> String {
+    let output = task_cmd_env(dir)
+        .current_dir(dir.path())
+        .args(["create", "wf"])
+        .output()
+        .unwrap();
+    created_id(&String::from_utf8_lossy(&output.stdout)).to_string()
+}
+
+#[test]
+fn workflow_rejects_unknown_status_with_suggestion() {
+    let dir = workflow_dir();
+    task_cmd_env(&dir)
+        .current_dir(dir.path())
+        .args(["create", "x", "--status", "inreivew"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("did you mean 'inreview'?"))
+        .stderr(predicate::str::contains("--force"));
+}
+
+#[test]
+fn workflow_resolves_aliases() {
+    let dir = workflow_dir();
+    let id = workflow_task(&dir);
+    task_cmd_env(&dir)
+        .current_dir(dir.path())
+        .args(["update", &id, "wip"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("TASK_DOING_{id}")));
+    task_cmd_env(&dir)
+        .current_dir(dir.path())
+        .args(["list", "wip"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(&id));
+}
+
+#[test]
+fn workflow_rejects_disallowed_transition() {
+    let dir = workflow_dir();
+    let id = workflow_task(&dir);
+    task_cmd_env(&dir)
+        .current_dir(dir.path())
+        .args(["update", &id, "inreview"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "transition 'todo' -> 'inreview' is not allowed",
+        ));
+    task_cmd_env(&dir)
+        .current_dir(dir.path())
+        .args(["update", &id, "inreview", "--force"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("TASK_INREVIEW_{id}")));
+}
+
+#[test]
+fn workflow_applies_in_subdirectories() {
+    let dir = workflow_dir();
+    let sub = dir.path().join("src/deep");
+    std::fs::create_dir_all(&sub).unwrap();
+    task_cmd_env(&dir)
+        .current_dir(&sub)
+        .args(["create", "x", "--status", "bogus"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn readiness_and_rollups_follow_aliased_todo_and_done() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join(".task.toml"),
+        "[workflow]\nstatuses = [\"open\", \"doing\", \"closed\"]\n\
+         [workflow.aliases]\ntodo = \"open\"\ndone = \"closed\"\n",
+    )
+    .unwrap();
+    let in_dir = |args: &[&str]| {
+        let mut cmd = task_cmd_env(&dir);
+        cmd.current_dir(dir.path()).args(args);
+        cmd
+    };
+    let create = |args: &[&str]| {
+        let output = in_dir(&[&["create"], args].concat()).output().unwrap();
+        created_id(&String::from_utf8_lossy(&output.stdout)).to_string()
+    };
+    let first = create(&["first"]);
+    let second = create(&["second", "--depends-on", &first]);
+    let epic = create(&["epic"]);
+    let part = create(&["part", "--parent", &epic]);
+    in_dir(&["update", &first, "done"]).assert().success();
+    in_dir(&["update", &part, "closed"]).assert().success();
+
+    let value = stdout_json(&mut in_dir(&["list", "--ready", "--json"]));
+    let ready: Vec<&str> = value
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ready, vec![second.as_str(), epic.as_str()]);
+    in_dir(&["list"])
+        .assert()
+        .stdout(predicate::str::contains("epic (1/1)"))
+        .stdout(predicate::str::contains("part").not());
+    in_dir(&["claim", "--next"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("TASK_DOING_{second}")));
+}
+
+#[test]
+fn claim_and_stale_follow_the_workflow() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join(".task.toml");
+    let in_dir = |args: &[&str]| {
+        let mut cmd = task_cmd_env(&dir);
+        cmd.current_dir(dir.path()).args(args);
+        cmd
+    };
+    std::fs::write(
+        &config,
+        "[workflow]\nstatuses = [\"todo\", \"in_progress\", \"done\"]\n",
+    )
+    .unwrap();
+    let id = workflow_task(&dir);
+    in_dir(&["claim", &id])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "`task claim` moves tasks to 'doing'",
+        ));
+
+    std::fs::write(
+        &config,
+        "[workflow]\nstatuses = [\"todo\", \"in_progress\", \"done\"]\n\
+         [workflow.aliases]\ndoing = \"in_progress\"\n\
+         [workflow.transitions]\ntodo = [\"done\"]\n",
+    )
+    .unwrap();
+    in_dir(&["claim", &id])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "transition 'todo' -> 'in_progress' is not allowed",
+        ));
+
+    std::fs::write(
+        &config,
+        "[workflow]\nstatuses = [\"todo\", \"in_progress\", \"done\"]\n\
+         [workflow.aliases]\ndoing = \"in_progress\"\n",
+    )
+    .unwrap();
+    in_dir(&["claim", &id, "--agent", "claude"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("TASK_IN_PROGRESS_{id}")));
+    in_dir(&["claim", &id, "--agent", "codex"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is claimed by claude"));
+    in_dir(&["stale", "--older-than", "0s"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(&id));
+    in_dir(&["stale", "--older-than", "0s", "--block"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "`task stale --block` moves tasks to 'blocked'",
+        ));
+}
+
+#[test]
+fn invalid_config_is_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join(".task.toml"), "[workflow\n").unwrap();
+    task_cmd_env(&dir)
+        .current_dir(dir.path())
+        .args(["list"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid config"));
+}
+
+// --- config ---
+
+#[test]
+fn config_list_shows_defaults_and_sources() {
+    let dir = tempfile::tempdir().unwrap();
+    task_cmd_env(&dir)
+        .current_dir(dir.path())
+        .args(["config", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("# env TASK_LOG_PATH"))
+        .stdout(predicate::str::contains(
+            "default_status = \"todo\"  # default",
+        ));
+}
+
+#[test]
+fn config_set_writes_project_file_and_takes_effect() {
+    let dir = tempfile::tempdir().unwrap();
+    task_cmd_env(&dir)
+        .current_dir(dir.path())
+        .args(["config", "set", "default_status", "inbox"])
+        .assert()
+        .success();
+    assert!(dir.path().join(".task.toml").exists());
+    task_cmd_env(&dir)
+        .current_dir(dir.path())
+        .args(["config", "get", "default_status"])
+        .assert()
+        .success()
+        .stdout("inbox\n");
+    let id = workflow_task(&dir);
+    let value = stdout_json(
+        task_cmd_env(&dir)
+            .current_dir(dir.path())
+            .args(["get", &id, "--json"]),
+    );
+    assert_eq!(value["task"]["status"], "inbox");
+}
+
+#[test]
+fn project_config_overrides_global() {
+    let dir = tempfile::tempdir().unwrap();
+    task_cmd_env(&dir)
+        .current_dir(dir.path())
+        .args(["config", "set", "--global", "project", "from-global"])
+        .assert()
+        .success();
+    assert!(dir.path().join("config/task/config.toml").exists());
+    task_cmd_env(&dir)
+        .current_dir(dir.path())
+        .args(["config", "get", "project"])
+        .assert()
+        .stdout("from-global\n");
+    std::fs::write(dir.path().join(".task.toml"), "project = \"owner/repo\"\n").unwrap();
+    let value = stdout_json(
+        task_cmd_env(&dir)
+            .current_dir(dir.path())
+            .args(["config", "get", "project", "--json"]),
+    );
+    assert_eq!(value["value"], "owner/repo");
+    assert!(value["source"].as_str().unwrap().starts_with("project "));
+}
+
+#[test]
+fn config_log_path_is_relative_to_config_file() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join(".task.toml"), "log_path = \"local.log\"\n").unwrap();
+    let mut cmd = cargo_bin_cmd!("task");
+    cmd.current_dir(dir.path())
+        .env_remove("TASK_LOG_PATH")
+        .env("XDG_CONFIG_HOME", dir.path().join("config"))
+        .args(["create", "local"])
+        .assert()
+        .success();
+    assert!(dir.path().join("local.log").exists());
+}
+
+#[test]
+fn config_columns_select_list_columns() {
+    let dir = tempfile::tempdir().unwrap();
+    task_cmd_env(&dir)
+        .current_dir(dir.path())
+        .args(["config", "set", "columns", "id,tags,title"])
+        .assert()
+        .success();
+    task_cmd_env(&dir)
+        .current_dir(dir.path())
+        .args(["create", "tagged", "--tag", "ui"])
+        .assert()
+        .success();
+    let output = task_cmd_env(&dir)
+        .current_dir(dir.path())
+        .args(["list"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next().unwrap(), "ID         TAGS TITLE");
+    assert!(lines.next().unwrap().ends_with(" ui   tagged"));
+}
+
+#[test]
+fn config_set_rejects_bad_values() {
+    let dir = tempfile::tempdir().unwrap();
+    for (key, value, err) in [
+        ("columns", "id,bogus", "unknown column 'bogus'"),
+        ("nope", "x", "unknown config key 'nope'"),
+        ("workflow", "x", "edit the config file directly"),
+    ] {
+        task_cmd_env(&dir)
+            .current_dir(dir.path())
+            .args(["config", "set", key, value])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(err));
+    }
+    assert!(!dir.path().join(".task.toml").exists());
+}
+
+// --- project identity ---
+
+fn git_repo(remote: Option<&str>) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir.path())
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {args:?}");
+    };
+    git(&["init", "-q"]);
+    if let Some(url) = remote {
+        git(&["remote", "add", "origin", url]);
+    }
+    dir
+}
+
+fn project_of(dir: &tempfile::TempDir, cwd: &std::path::Path) -> String {
+    let output = task_cmd_env(dir)
+        .current_dir(cwd)
+        .args(["project", "show"])
+        .output()
+        .unwrap();
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn project_comes_from_origin_remote() {
+    let repo = git_repo(Some("git@github.com:owner/repo.git"));
+    let sub = repo.path().join("src");
+    std::fs::create_dir(&sub).unwrap();
+    assert_eq!(project_of(&repo, &sub), "owner/repo");
+    let value = stdout_json(
+        task_cmd_env(&repo)
+            .current_dir(&sub)
+            .args(["create", "x", "--json"]),
+    );
+    assert_eq!(value["project"], "owner/repo");
+}
+
+#[test]
+fn project_falls_back_to_toplevel_without_remote() {
+    let repo = git_repo(None);
+    let toplevel = repo.path().canonicalize().unwrap();
+    assert_eq!(project_of(&repo, repo.path()), toplevel.to_str().unwrap());
+}
+
+#[test]
+fn project_alias_maps_old_values_without_rewriting_log() {
+    let repo = git_repo(Some("https://github.com/owner/repo.git"));
+    let log = repo.path().join("tasks.log");
+    std::fs::write(
+        &log,
+        "{\"ts\":\"2026-02-22T14:30:00+09:00\",\"id\":\"0ld00001\",\"project\":\"/old/checkout\",\"status\":\"todo\",\"title\":\"old\",\"description\":\"\",\"note\":\"\"}\n",
+    )
+    .unwrap();
+    let before = std::fs::read_to_string(&log).unwrap();
+    task_cmd_env(&repo)
+        .current_dir(repo.path())
+        .args(["list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("0ld00001").not());
+    task_cmd_env(&repo)
+        .current_dir(repo.path())
+        .args(["project", "alias", "/old/checkout"])
+        .assert()
+        .success()
+        .stdout("/old/checkout -> owner/repo\n");
+    task_cmd_env(&repo)
+        .current_dir(repo.path())
+        .args(["list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("0ld00001"));
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), before);
+    task_cmd_env(&repo)
+        .current_dir(repo.path())
+        .args(["project", "alias", "owner/repo", "--to", "/old/checkout"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("alias cycle"));
+}
+
+#[test]
+fn project_migrate_aliases_paths_of_known_checkouts() {
+    let repo = git_repo(Some("git@github.com:owner/repo.git"));
+    let toplevel = repo.path().canonicalize().unwrap();
+    let log = repo.path().join("tasks.log");
+    let line = |id: &str, project: &str| {
+        serde_json::json!({
+            "ts": "2026-02-22T14:30:00+09:00", "id": id, "project": project,
+            "status": "todo", "title": id, "description": "", "note": "",
+        })
+        .to_string()
+            + "\n"
+    };
+    std::fs::write(
+        &log,
+        line("a0000001", toplevel.to_str().unwrap()) + &line("b0000001", "/gone/checkout"),
+    )
+    .unwrap();
+    task_cmd_env(&repo)
+        .current_dir(repo.path())
+        .args(["project", "migrate", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "would alias {} -> owner/repo",
+            toplevel.display()
+        )))
+        .stderr(predicate::str::contains("skipped /gone/checkout"));
+    task_cmd_env(&repo)
+        .current_dir(repo.path())
+        .args(["project", "migrate"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("aliased "));
+    task_cmd_env(&repo)
+        .current_dir(repo.path())
+        .args(["list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a0000001"));
+    task_cmd_env(&repo)
+        .current_dir(repo.path())
+        .args(["project", "migrate"])
+        .assert()
+        .success()
+        .stdout("nothing to migrate\n");
+}
+
+#[test]
+fn worktrees_share_a_project_and_record_branch() {
+    let repo = tempfile::tempdir().unwrap();
+    let main = repo.path().join("main");
+    let feature = repo.path().join("feature");
+    let git = |dir: &std::path::Path, args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .args(["-c", "user.name=t", "-c", "user.email=t@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?}: {output:?}");
+    };
+    git(repo.path(), &["init", "-q", "main"]);
+    git(&main, &["commit", "-q", "--allow-empty", "-m", "init"]);
+    git(
+        &main,
+        &["worktree", "add", "-q", "../feature", "-b", "feat-x"],
+    );
+
+    let id = stdout_json(task_cmd_env(&repo).current_dir(&feature).args([
+        "create",
+        "from worktree",
+        "--json",
+    ]))["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(project_of(&repo, &feature), project_of(&repo, &main));
+
+    let value = stdout_json(
+        task_cmd_env(&repo)
+            .current_dir(&main)
+            .args(["get", &id, "--json"]),
+    );
+    assert_eq!(value["task"]["branch"], "feat-x");
+    assert!(
+        value["task"]["worktree"]
+            .as_str()
+            .unwrap()
+            .ends_with("/feature")
+    );
+    task_cmd_env(&repo)
+        .current_dir(&main)
+        .args(["list", "--columns", "id,branch,worktree,title"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("BRANCH"))
+        .stdout(predicate::str::contains("feat-x"));
+}
+
+// --- actor / session ---
+
+#[test]
+fn actor_and_session_come_from_env() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let value = stdout_json(
+        task_cmd_env(&dir)
+            .env("TASK_ACTOR", "codex")
+            .env("TASK_SESSION", "sess-1234567890")
+            .args(["create", "x", "--json"]),
+    );
+    assert_eq!(value["actor"], "codex");
+    assert_eq!(value["session"], "sess-1234567890");
+}
+
+#[test]
+fn actor_flag_overrides_env() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let value = stdout_json(
+        task_cmd_env(&dir)
+            .env("TASK_ACTOR", "codex")
+            .args(["create", "x", "--actor", "alice", "--json"]),
+    );
+    assert_eq!(value["actor"], "alice");
+}
+
+#[test]
+fn actor_is_detected_from_agent_env_or_defaults_to_human() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let bare = || {
+        let mut cmd = task_cmd_env(&dir);
+        cmd.env_clear()
+            .env("PATH", std::env::var_os("PATH").unwrap())
+            .env("HOME", dir.path())
+            .env("TASK_LOG_PATH", dir.path().join("tasks.log"));
+        cmd
+    };
+    let value = stdout_json(
+        bare()
+            .env("GEMINI_CLI", "1")
+            .args(["create", "x", "--json"]),
+    );
+    assert_eq!(value["actor"], "gemini");
+    let value = stdout_json(bare().args(["create", "y", "--json"]));
+    assert_eq!(value["actor"], "human");
+    assert!(value.get("session").is_none());
+}
+
+#[test]
+fn get_history_shows_who_moved_the_task() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["x", "--actor", "human"]);
+    task_cmd_env(&dir)
+        .env("TASK_SESSION", "abcdef0123456789")
+        .args(["update", &id, "blocked", "need input", "--actor", "claude"])
+        .assert()
+        .success();
+    let output = task_cmd_env(&dir).args(["get", &id]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let blocked = stdout.lines().find(|l| l.contains("blocked")).unwrap();
+    assert!(blocked.contains("claude@abcdef01 need input"), "{stdout}");
+    assert!(
+        stdout
+            .lines()
+            .any(|l| l.contains("todo") && l.contains("human"))
+    );
+}
+
+#[test]
+fn list_filters_by_last_actor() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let a = create_task(&dir, &["a", "--actor", "claude"]);
+    let b = create_task(&dir, &["b", "--actor", "claude"]);
+    task_cmd_env(&dir)
+        .args(["update", &b, "doing", "--actor", "codex"])
+        .assert()
+        .success();
+    let value = stdout_json(task_cmd_env(&dir).args(["list", "--actor", "codex", "--json"]));
+    let ids: Vec<&str> = value
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, vec![b.as_str()]);
+    task_cmd_env(&dir)
+        .args(["list", "--actor", "claude"])
+        .assert()
+        .stdout(predicate::str::contains(&a))
+        .stdout(predicate::str::contains(&b).not());
+}
+
+#[test]
+fn claim_agent_defaults_to_actor() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["x"]);
+    let value = stdout_json(
+        task_cmd_env(&dir)
+            .env("TASK_ACTOR", "codex")
+            .args(["claim", &id, "--json"]),
+    );
+    assert_eq!(value["claimed_by"], "codex");
+}
+
+// --- doctor ---
+
+#[test]
+fn doctor_clean_log_ok() {
+    let (_cmd, dir) = task_cmd_with_log();
+    create_task(&dir, &["fine"]);
+    task_cmd_env(&dir)
+        .arg("doctor")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("OK: 1 entries"));
+}
+
+#[test]
+fn doctor_reports_and_quarantines_bad_lines() {
+    let (_cmd, dir) = task_cmd_with_log();
+    let id = create_task(&dir, &["survivor"]);
+    let log_path = dir.path().join("tasks.log");
+    let mut raw = std::fs::read_to_string(&log_path).unwrap();
+    raw.push_str("not json\n{\"ts\":\"2026-02-22T14:30:00+09:00\",\"id\"");
+    std::fs::write(&log_path, raw).unwrap();
+
+    task_cmd_env(&dir)
+        .args(["list", "--all"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(&id))
+        .stderr(predicate::str::contains("skipped 1 malformed line(s)"));
+
+    task_cmd_env(&dir)
+        .arg("doctor")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("line 2:"))
+        .stdout(predicate::str::contains("line 3:"))
+        .stdout(predicate::str::contains("truncated final line"));
+
+    task_cmd_env(&dir)
+        .args(["doctor", "--quarantine"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Quarantined 2 line(s)"));
+    assert!(dir.path().join("tasks.log.quarantine").exists());
+    assert!(std::fs::read_to_string(&log_path).unwrap().starts_with("{"));
+
+    task_cmd_env(&dir)
+        .args(["list", "--all"])
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+    task_cmd_env(&dir).arg("doctor").assert().success();
 }
 
 // --- init ---